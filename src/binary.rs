@@ -0,0 +1,600 @@
+use std::convert::TryInto;
+
+use crate::lossless::PageEncoding;
+use crate::{
+    decode_cell_color, CellColor, Encoding, Fumen, Page, Piece, PieceType, Prefix, RotationState,
+    Version, ViewMode, MAX_COMMENT_LENGTH
+};
+
+const MAGIC: &[u8] = b"FUMN";
+const VERSION: u8 = 1;
+
+impl Fumen {
+    /// Serializes into a compact binary format.
+    ///
+    /// Unlike [`Fumen::encode`], this never loses information: [`Fumen::from_bytes`] always
+    /// returns an identical fumen, including the [`Fumen::encoding`] if there is one.
+    ///
+    /// # Format
+    ///
+    /// All multi-byte integers are little-endian. Varints are LEB128, and signed varints are
+    /// zigzag encoded first. The data is laid out as follows:
+    ///
+    /// - The magic bytes `FUMN`.
+    /// - The format version, currently 1.
+    /// - A flags byte: bit 0 is the guideline flag, bit 1 is set if an encoding section follows
+    ///   the pages.
+    /// - The number of pages as a varint.
+    /// - Each page:
+    ///   - A flags byte: bit 0 is set if there is a piece, bit 1 is the rise flag, bit 2 the
+    ///     mirror flag, bit 3 the lock flag, bit 4 is set if there is a comment and bit 5 is set
+    ///     if the field differs from the one fumen would create from the previous page.
+    ///   - If there is a piece, a byte with the [`PieceType`] in bits 0-2 and the
+    ///     [`RotationState`] in bits 3-4, then the x and y coordinates as signed varints.
+    ///   - If the field differs, a 3 byte mask of the rows which differ, with bit 23 for the
+    ///     garbage row, then the cells of each of those rows from the bottom up. Cells are 4 bits
+    ///     each, as in [`CellColor`], low bits first, so a row takes 5 bytes.
+    ///   - If there is a comment, its length in bytes as a varint followed by it in UTF-8.
+    /// - If the encoding section is present, the details of the [`Encoding`]: the view mode and
+    ///   version as bytes, then the separator count and offsets as varints, then for each page:
+    ///   - A byte which is 1 if the page has its own field runs, followed by the number of runs,
+    ///     the runs and the length of the empty field sequence it started as varints.
+    ///   - The piece and flags number as a varint.
+    ///   - A byte which is 1 if there is a comment, followed by its length as a varint and the
+    ///     escaped text.
+    /// - A CRC-32 (IEEE) of everything before it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a piece which locks lies outside of the field.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION);
+        data.push(self.guideline as u8 | (self.encoding.is_some() as u8) << 1);
+        write_varint(&mut data, self.pages.len() as u64);
+
+        let mut prev = Page::default();
+        for page in &self.pages {
+            let field_mask = (0..23)
+                .filter(|&y| page.field[y] != prev.field[y])
+                .chain(Some(23).filter(|_| page.garbage_row != prev.garbage_row))
+                .fold(0u32, |mask, y| mask | 1 << y);
+
+            data.push(
+                page.piece.is_some() as u8 |
+                (page.rise as u8) << 1 |
+                (page.mirror as u8) << 2 |
+                (page.lock as u8) << 3 |
+                (page.comment.is_some() as u8) << 4 |
+                ((field_mask != 0) as u8) << 5
+            );
+            if let Some(piece) = page.piece {
+                data.push(piece.kind as u8 | (piece.rotation as u8) << 3);
+                write_signed_varint(&mut data, piece.x as i64);
+                write_signed_varint(&mut data, piece.y as i64);
+            }
+            if field_mask != 0 {
+                data.extend_from_slice(&field_mask.to_le_bytes()[..3]);
+                for y in 0..24 {
+                    if field_mask & 1 << y != 0 {
+                        let row = if y == 23 { &page.garbage_row } else { &page.field[y] };
+                        for cells in row.chunks(2) {
+                            data.push(cells[0] as u8 | (cells[1] as u8) << 4);
+                        }
+                    }
+                }
+            }
+            if let Some(ref comment) = page.comment {
+                write_varint(&mut data, comment.len() as u64);
+                data.extend_from_slice(comment.as_bytes());
+            }
+            prev = page.next_page();
+        }
+
+        if let Some(ref encoding) = self.encoding {
+            write_encoding(&mut data, encoding);
+        }
+
+        let checksum = crc32(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        data
+    }
+
+    /// Deserializes from the binary format written by [`Fumen::to_bytes`].
+    ///
+    /// The checksum is verified before anything else, and every value is checked, so corrupt
+    /// data is reported as an error. This does not panic on any input.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Fumen, FromBytesError> {
+        if bytes.len() < MAGIC.len() + 1 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(FromBytesError::InvalidHeader);
+        }
+        if bytes[MAGIC.len()] != VERSION {
+            return Err(FromBytesError::UnsupportedVersion { version: bytes[MAGIC.len()] });
+        }
+        if bytes.len() < MAGIC.len() + 6 {
+            return Err(FromBytesError::Truncated { offset: bytes.len() });
+        }
+        let (data, checksum) = bytes.split_at(bytes.len() - 4);
+        if crc32(data).to_le_bytes() != checksum {
+            return Err(FromBytesError::ChecksumMismatch);
+        }
+
+        let mut reader = ByteReader { data, offset: MAGIC.len() + 1 };
+        let flags = reader.byte()?;
+        if flags & !0b11 != 0 {
+            return Err(reader.invalid(1));
+        }
+        let mut fumen = Fumen { guideline: flags & 1 != 0, ..Fumen::default() };
+        let count = reader.varint()?;
+
+        for _ in 0..count {
+            let mut page = match fumen.pages.last() {
+                Some(p) => p.next_page(),
+                None => Page::default()
+            };
+            let flags = reader.byte()?;
+            if flags & !0b11_1111 != 0 {
+                return Err(reader.invalid(1));
+            }
+            page.rise = flags & 0b10 != 0;
+            page.mirror = flags & 0b100 != 0;
+            page.lock = flags & 0b1000 != 0;
+            page.piece = None;
+            if flags & 0b1 != 0 {
+                let kind = reader.byte()?;
+                let (kind, rotation) = match (piece_type(kind & 0b111), rotation(kind >> 3)) {
+                    (Some(kind), Some(rotation)) => (kind, rotation),
+                    _ => return Err(reader.invalid(1))
+                };
+                let start = reader.offset;
+                let x = reader.coordinate()?;
+                let y = reader.coordinate()?;
+                let piece = Piece { kind, rotation, x, y };
+                // locking a piece outside of the field would panic
+                if page.lock && !piece.in_field() {
+                    return Err(FromBytesError::InvalidValue { offset: start });
+                }
+                page.piece = Some(piece);
+            }
+            if flags & 0b10_0000 != 0 {
+                let mask = reader.take(3)?;
+                let mask = u32::from_le_bytes([mask[0], mask[1], mask[2], 0]);
+                if mask == 0 {
+                    return Err(reader.invalid(3));
+                }
+                for y in 0..24 {
+                    if mask & 1 << y != 0 {
+                        let row = reader.row()?;
+                        if y == 23 {
+                            page.garbage_row = row;
+                        } else {
+                            page.field[y] = row;
+                        }
+                    }
+                }
+            }
+            if flags & 0b1_0000 != 0 {
+                page.comment = Some(reader.string()?);
+            }
+            fumen.pages.push(page);
+        }
+
+        if flags & 0b10 != 0 {
+            fumen.encoding = Some(reader.encoding()?);
+        }
+        if reader.offset != data.len() {
+            return Err(FromBytesError::TrailingData { offset: reader.offset });
+        }
+        Ok(fumen)
+    }
+}
+
+fn write_encoding(data: &mut Vec<u8>, encoding: &Encoding) {
+    data.push(match encoding.prefix.mode {
+        ViewMode::View => 0,
+        ViewMode::Mobile => 1,
+        ViewMode::Data => 2,
+        ViewMode::Editor => 3
+    });
+    data.push(match encoding.prefix.version {
+        Version::V110 => 0,
+        Version::V115 => 1
+    });
+    write_varint(data, encoding.separators.len() as u64);
+    for &offset in &encoding.separators {
+        write_varint(data, offset as u64);
+    }
+    write_varint(data, encoding.pages.len() as u64);
+    for page in &encoding.pages {
+        data.push(page.field.is_some() as u8);
+        if let Some(ref runs) = page.field {
+            write_varint(data, runs.len() as u64);
+            for &run in runs {
+                write_varint(data, run as u64);
+            }
+            write_varint(data, page.empty_fields as u64);
+        }
+        write_varint(data, page.action as u64);
+        data.push(page.comment.is_some() as u8);
+        if let Some(ref comment) = page.comment {
+            write_varint(data, comment.len() as u64);
+            data.extend_from_slice(comment.as_bytes());
+        }
+    }
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn write_signed_varint(data: &mut Vec<u8>, value: i64) {
+    write_varint(data, (value << 1 ^ value >> 63) as u64);
+}
+
+fn piece_type(value: u8) -> Option<PieceType> {
+    Some(match value {
+        1 => PieceType::I,
+        2 => PieceType::L,
+        3 => PieceType::O,
+        4 => PieceType::Z,
+        5 => PieceType::T,
+        6 => PieceType::J,
+        7 => PieceType::S,
+        _ => return None
+    })
+}
+
+fn rotation(value: u8) -> Option<RotationState> {
+    Some(match value {
+        0 => RotationState::South,
+        1 => RotationState::East,
+        2 => RotationState::North,
+        3 => RotationState::West,
+        _ => return None
+    })
+}
+
+/// Reads values out of binary data, keeping track of where we are for error reporting.
+struct ByteReader<'a> {
+    data: &'a [u8],
+    offset: usize
+}
+
+impl ByteReader<'_> {
+    /// An error for the value of `length` bytes which was just read.
+    fn invalid(&self, length: usize) -> FromBytesError {
+        FromBytesError::InvalidValue { offset: self.offset - length }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&[u8], FromBytesError> {
+        let bytes = self.data.get(self.offset..)
+            .and_then(|rest| rest.get(..length))
+            .ok_or(FromBytesError::Truncated { offset: self.data.len() })?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, FromBytesError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, FromBytesError> {
+        let start = self.offset;
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                if shift == 63 && byte > 1 {
+                    break;
+                }
+                return Ok(value);
+            }
+        }
+        Err(FromBytesError::InvalidValue { offset: start })
+    }
+
+    fn signed_varint(&mut self) -> Result<i64, FromBytesError> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn coordinate(&mut self) -> Result<i32, FromBytesError> {
+        let start = self.offset;
+        let value = self.signed_varint()?;
+        value.try_into().map_err(|_| FromBytesError::InvalidValue { offset: start })
+    }
+
+    /// Reads a varint which is used as a length or index.
+    fn usize(&mut self) -> Result<usize, FromBytesError> {
+        let start = self.offset;
+        let value = self.varint()?;
+        value.try_into().map_err(|_| FromBytesError::InvalidValue { offset: start })
+    }
+
+    fn row(&mut self) -> Result<[CellColor; 10], FromBytesError> {
+        let mut row = [CellColor::Empty; 10];
+        for x in (0..10).step_by(2) {
+            let byte = self.byte()?;
+            match (decode_cell_color(byte as usize & 0xF), decode_cell_color(byte as usize >> 4)) {
+                (Some(a), Some(b)) => {
+                    row[x] = a;
+                    row[x + 1] = b;
+                }
+                _ => return Err(self.invalid(1))
+            }
+        }
+        Ok(row)
+    }
+
+    fn string(&mut self) -> Result<String, FromBytesError> {
+        let length = self.usize()?;
+        let start = self.offset;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| FromBytesError::InvalidValue { offset: start })
+    }
+
+    fn encoding(&mut self) -> Result<Encoding, FromBytesError> {
+        let mode = match self.byte()? {
+            0 => ViewMode::View,
+            1 => ViewMode::Mobile,
+            2 => ViewMode::Data,
+            3 => ViewMode::Editor,
+            _ => return Err(self.invalid(1))
+        };
+        let version = match self.byte()? {
+            0 => Version::V110,
+            1 => Version::V115,
+            _ => return Err(self.invalid(1))
+        };
+        let mut separators = vec![];
+        for _ in 0..self.varint()? {
+            separators.push(self.usize()?);
+        }
+        // pages may have been added or removed since decoding, so the count can differ
+        let mut encoding = vec![];
+        for _ in 0..self.varint()? {
+            let mut empty_fields = 0;
+            let field = match self.byte()? {
+                0 => None,
+                1 => {
+                    let mut runs = vec![];
+                    for _ in 0..self.varint()? {
+                        let start = self.offset;
+                        match self.varint()? {
+                            // runs are written as 2 base64 digits
+                            run @ 0..=0xFFF => runs.push(run as u16),
+                            _ => return Err(FromBytesError::InvalidValue { offset: start })
+                        }
+                    }
+                    let start = self.offset;
+                    empty_fields = match self.usize()? {
+                        // the length is written as 1 base64 digit
+                        length @ 0..=63 => length,
+                        _ => return Err(FromBytesError::InvalidValue { offset: start })
+                    };
+                    Some(runs)
+                }
+                _ => return Err(self.invalid(1))
+            };
+            let start = self.offset;
+            let action = match self.usize()? {
+                // the piece and flags number is written as 3 base64 digits
+                action @ 0..=0x3FFFF => action,
+                _ => return Err(FromBytesError::InvalidValue { offset: start })
+            };
+            let comment = match self.byte()? {
+                0 => None,
+                1 => {
+                    let start = self.offset;
+                    let comment = self.string()?;
+                    let valid = comment.len() <= MAX_COMMENT_LENGTH &&
+                        comment.bytes().all(|b| (0x20..0x7F).contains(&b));
+                    if !valid {
+                        return Err(FromBytesError::InvalidValue { offset: start });
+                    }
+                    Some(comment)
+                }
+                _ => return Err(self.invalid(1))
+            };
+            encoding.push(PageEncoding { field, empty_fields, action, comment });
+        }
+        Ok(Encoding { prefix: Prefix { mode, version }, separators, pages: encoding })
+    }
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &b| CRC_TABLE[(crc as u8 ^ b) as usize] ^ crc >> 8)
+}
+
+/// The error type for [`Fumen::from_bytes`].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum FromBytesError {
+    /// The data does not start with the magic bytes of the format.
+    InvalidHeader,
+    /// The data was written by an unsupported version of the format.
+    UnsupportedVersion { version: u8 },
+    /// The checksum doesn't match the data, so it has been corrupted.
+    ChecksumMismatch,
+    /// The data ended in the middle of a value.
+    Truncated { offset: usize },
+    /// A value is out of range.
+    InvalidValue { offset: usize },
+    /// There is more data after the end of the fumen.
+    TrailingData { offset: usize }
+}
+
+impl std::fmt::Display for FromBytesError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FromBytesError::InvalidHeader => write!(fmt, "the data is not a binary fumen"),
+            FromBytesError::UnsupportedVersion { version } => {
+                write!(fmt, "unsupported binary fumen version {}", version)
+            }
+            FromBytesError::ChecksumMismatch => write!(fmt, "checksum mismatch"),
+            FromBytesError::Truncated { offset } => {
+                write!(fmt, "data is truncated at offset {}", offset)
+            }
+            FromBytesError::InvalidValue { offset } => {
+                write!(fmt, "invalid value at offset {}", offset)
+            }
+            FromBytesError::TrailingData { offset } => {
+                write!(fmt, "unexpected data at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FromBytesError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn sample() -> Fumen {
+        let mut fumen = Fumen { guideline: false, ..Fumen::default() };
+        let page = fumen.add_page();
+        page.field[0] = [CellColor::Grey; 10];
+        page.field[0][4] = CellColor::Empty;
+        page.field[5][9] = CellColor::S;
+        page.garbage_row = [CellColor::Grey; 10];
+        page.piece = Some(Piece { kind: PieceType::I, rotation: RotationState::East, x: 4, y: 2 });
+        page.comment = Some("Hello World! \u{1F600}".to_owned());
+        let page = fumen.add_page();
+        page.rise = true;
+        page.mirror = true;
+        let page = fumen.add_page();
+        page.lock = false;
+        page.piece = Some(Piece { kind: PieceType::T, rotation: RotationState::South, x: 1, y: 1 });
+        page.comment = Some(String::new());
+        let page = fumen.add_page();
+        page.lock = false;
+        page.piece = Some(Piece { kind: PieceType::O, rotation: RotationState::West, x: 40, y: 0 });
+        fumen
+    }
+
+    #[test]
+    fn round_trip() {
+        for fumen in &[
+            Fumen::default(),
+            sample(),
+            Fumen::decode_lossless("v115@vhAAgWQA?IoMDEvoo2AXXaDE?koA6A").unwrap(),
+            Fumen::decode_lossless("v115@vhEAgHAAA").unwrap(),
+        ] {
+            let bytes = fumen.to_bytes();
+            assert_eq!(&bytes[..5], b"FUMN\x01");
+            let decoded = Fumen::from_bytes(&bytes).unwrap();
+            assert_eq!(&decoded, fumen);
+            assert_eq!(decoded.encoding, fumen.encoding);
+        }
+
+        // the encoding is kept as is when pages are added or removed
+        let mut fumen = Fumen::decode_lossless("v115@vhAAgWQA?IoMDEvoo2AXXaDE?koA6A").unwrap();
+        fumen.add_page();
+        let decoded = Fumen::from_bytes(&fumen.to_bytes()).unwrap();
+        assert_eq!((&decoded, &decoded.encoding), (&fumen, &fumen.encoding));
+        fumen.pages.clear();
+        let decoded = Fumen::from_bytes(&fumen.to_bytes()).unwrap();
+        assert_eq!((&decoded, &decoded.encoding), (&fumen, &fumen.encoding));
+
+        let mut fumen = Fumen::default();
+        for _ in 0..100 {
+            fumen.add_page();
+        }
+        fumen.pages[50].field[0][0] = CellColor::Grey;
+        assert!(fumen.to_bytes().len() < fumen.encode().len());
+    }
+
+    #[test]
+    fn corruption() {
+        let bytes = sample().to_bytes();
+        for length in 0..bytes.len() {
+            assert!(Fumen::from_bytes(&bytes[..length]).is_err());
+        }
+        for i in 0..bytes.len() {
+            for bit in 0..8 {
+                let mut corrupted = bytes.clone();
+                corrupted[i] ^= 1 << bit;
+                assert!(Fumen::from_bytes(&corrupted).is_err());
+            }
+        }
+        assert_eq!(Fumen::from_bytes(b"v115@vhAAgH"), Err(FromBytesError::InvalidHeader));
+        assert_eq!(
+            Fumen::from_bytes(b"FUMN\x02"),
+            Err(FromBytesError::UnsupportedVersion { version: 2 })
+        );
+    }
+
+    #[test]
+    fn checked_values() {
+        // data with valid checksums but invalid contents
+        let with_checksum = |data: &[u8]| {
+            let mut data = data.to_vec();
+            let checksum = super::crc32(&data);
+            data.extend_from_slice(&checksum.to_le_bytes());
+            data
+        };
+        assert_eq!(
+            Fumen::from_bytes(&with_checksum(b"FUMN\x01\x00\x01\x41")),
+            Err(FromBytesError::InvalidValue { offset: 7 })
+        );
+        assert_eq!(
+            Fumen::from_bytes(&with_checksum(b"FUMN\x01\x00\x01\x01\x00")),
+            Err(FromBytesError::InvalidValue { offset: 8 })
+        );
+        assert_eq!(
+            Fumen::from_bytes(&with_checksum(b"FUMN\x01\x00\x01\x09\x12\x02\x50")),
+            Err(FromBytesError::InvalidValue { offset: 9 })
+        );
+        // a locked piece with x = i32::MAX
+        assert_eq!(
+            Fumen::from_bytes(&with_checksum(
+                b"FUMN\x01\x00\x01\x09\x12\xFE\xFF\xFF\xFF\x0F\x00"
+            )),
+            Err(FromBytesError::InvalidValue { offset: 9 })
+        );
+        assert_eq!(
+            Fumen::from_bytes(&with_checksum(b"FUMN\x01\x00\xFF\xFF\xFF\xFF\x0F")),
+            Err(FromBytesError::Truncated { offset: 11 })
+        );
+        assert_eq!(
+            Fumen::from_bytes(&with_checksum(
+                b"FUMN\x01\x02\x01\x00\x00\x01\x00\x01\x00\x80\x80\x10"
+            )),
+            Err(FromBytesError::InvalidValue { offset: 13 })
+        );
+        assert_eq!(
+            Fumen::from_bytes(&with_checksum(b"FUMN\x01\x00\x00\x00")),
+            Err(FromBytesError::TrailingData { offset: 7 })
+        );
+        assert_eq!(
+            Fumen::from_bytes(&with_checksum(b"FUMN\x01\x00\x01\x20\x01\x00\x00\x99")),
+            Err(FromBytesError::InvalidValue { offset: 11 })
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{CellColor, Field, Page, Piece};
+
+/// A field which only keeps track of which cells are filled, for fast simulation.
+///
+/// Each row is stored as a `u16` with bit `x` set if the cell in column `x` is filled, and the
+/// bits above bit 9 are always clear. This is only the occupancy of a field: colors and the
+/// garbage row are not stored, so converting a [`Page`] to a `BitField` and back loses them.
+///
+/// With the `serde` feature this is serialized as an array of the rows.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "[u16; 23]", into = "[u16; 23]"))]
+pub struct BitField {
+    /// y-up
+    rows: [u16; 23]
+}
+
+impl BitField {
+    /// A row with every cell filled.
+    pub const FULL_ROW: u16 = 0b11_1111_1111;
+
+    /// The rows from the bottom up.
+    pub fn rows(&self) -> &[u16; 23] {
+        &self.rows
+    }
+
+    /// Sets the row at height `y`. Bits above bit 9 are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `y` is 23 or more.
+    pub fn set_row(&mut self, y: usize, row: u16) {
+        self.rows[y] = row & BitField::FULL_ROW;
+    }
+
+    /// Whether the cell is filled. Cells outside of the field are filled, except for the ones
+    /// above it.
+    pub fn occupied(&self, x: i32, y: i32) -> bool {
+        if !(0..10).contains(&x) || y < 0 {
+            return true;
+        }
+        self.rows.get(y as usize).is_some_and(|&row| row & 1 << x != 0)
+    }
+
+    /// Fills or empties a cell.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is outside of the field.
+    pub fn set(&mut self, x: i32, y: i32, filled: bool) {
+        assert!((0..10).contains(&x) && (0..23).contains(&y), "cell is outside of the field");
+        if filled {
+            self.rows[y as usize] |= 1 << x;
+        } else {
+            self.rows[y as usize] &= !(1 << x);
+        }
+    }
+
+    /// Whether the piece overlaps a filled cell or lies partly outside of the field.
+    pub fn collides(&self, piece: &Piece) -> bool {
+        !piece.checked_cells().is_some_and(|cells| cells.iter().all(|&(x, y)| !self.blocked(x, y)))
+    }
+
+    /// Whether a piece can't have a cell here, because it is filled or outside of the field.
+    pub(crate) fn blocked(&self, x: i32, y: i32) -> bool {
+        y >= 23 || self.occupied(x, y)
+    }
+
+    /// Fills the cells of the piece which lie inside of the field.
+    pub fn place(&mut self, piece: &Piece) {
+        for &(x, y) in piece.checked_cells().iter().flatten() {
+            if (0..10).contains(&x) && (0..23).contains(&y) {
+                self.set(x, y, true);
+            }
+        }
+    }
+
+    /// Removes filled rows, moving the rows above them down.
+    ///
+    /// Returns a mask of the rows which were cleared, with bit `y` set if row `y` was cleared.
+    pub fn clear_lines(&mut self) -> u32 {
+        let mut cleared = 0;
+        let mut y = 0;
+        for i in 0..23 {
+            if self.rows[i] == BitField::FULL_ROW {
+                cleared |= 1 << i;
+            } else {
+                self.rows[y] = self.rows[i];
+                y += 1;
+            }
+        }
+        for row in &mut self.rows[y..] {
+            *row = 0;
+        }
+        cleared
+    }
+
+    /// Whether every cell is empty.
+    pub fn is_empty(&self) -> bool {
+        self.rows.iter().all(|&row| row == 0)
+    }
+}
+
+impl From<[u16; 23]> for BitField {
+    /// Creates a field from rows from the bottom up. Bits above bit 9 are ignored.
+    fn from(rows: [u16; 23]) -> BitField {
+        let mut field = BitField::default();
+        for (y, &row) in rows.iter().enumerate() {
+            field.set_row(y, row);
+        }
+        field
+    }
+}
+
+impl From<BitField> for [u16; 23] {
+    fn from(field: BitField) -> [u16; 23] {
+        field.rows
+    }
+}
+
+impl From<&Field> for BitField {
+    /// Takes the filled cells of the field.
+    fn from(field: &Field) -> BitField {
+        let mut bits = BitField::default();
+        for (y, row) in field.iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                if cell != CellColor::Empty {
+                    bits.rows[y] |= 1 << x;
+                }
+            }
+        }
+        bits
+    }
+}
+
+impl From<&Page> for BitField {
+    /// Takes the filled cells of the page's field. The garbage row is not included.
+    fn from(page: &Page) -> BitField {
+        BitField::from(&page.field)
+    }
+}
+
+impl From<BitField> for Page {
+    /// Creates a page with the filled cells of the field in grey.
+    ///
+    /// This loses the colors of a page the field was created from, and its garbage row.
+    fn from(field: BitField) -> Page {
+        let mut page = Page::default();
+        for (y, &row) in field.rows.iter().enumerate() {
+            for x in 0..10 {
+                if row & 1 << x != 0 {
+                    page.field[y][x] = CellColor::Grey;
+                }
+            }
+        }
+        page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn collisions() {
+        let mut field = BitField::default();
+        field.set_row(0, 0b1);
+        let mut piece = Piece { kind: PieceType::I, rotation: RotationState::East, x: 0, y: 3 };
+        assert!(!field.collides(&piece));
+        piece.y = 2;
+        assert!(field.collides(&piece));
+        piece.y = 22;
+        assert!(field.collides(&piece));
+        assert!(field.occupied(-1, 5) && field.occupied(10, 5) && field.occupied(4, -1));
+        assert!(!field.occupied(4, 23));
+    }
+
+    #[test]
+    fn line_clears() {
+        let mut field = BitField::default();
+        field.set_row(0, 0b11_1111_1110);
+        field.set_row(1, 0b1111_1111_1111_1110);
+        field.set_row(2, 0b00_0000_0001);
+        assert_eq!(field.rows()[1], 0b11_1111_1110);
+        field.place(&Piece { kind: PieceType::I, rotation: RotationState::East, x: 0, y: 2 });
+        assert_eq!(field.clear_lines(), 0b11);
+        assert_eq!(field.rows()[..3], [0b1, 0b1, 0]);
+        field.set(0, 0, false);
+        field.set(0, 1, false);
+        assert!(field.is_empty());
+    }
+
+    #[test]
+    fn page_conversion() {
+        let mut page = Page::default();
+        page.field[0] = [CellColor::Grey; 10];
+        page.field[0][4] = CellColor::Empty;
+        page.field[3][9] = CellColor::T;
+        page.garbage_row[0] = CellColor::Grey;
+        let field = BitField::from(&page);
+        assert_eq!(field.rows()[0], 0b11_1110_1111);
+        assert_eq!(field.rows()[3], 0b10_0000_0000);
+
+        page.field[3][9] = CellColor::Grey;
+        page.garbage_row[0] = CellColor::Empty;
+        assert_eq!(Page::from(field), page);
+        let mut fumen = Fumen::default();
+        fumen.add_page().piece = Some(
+            Piece { kind: PieceType::T, rotation: RotationState::South, x: 5, y: 1 }
+        );
+        fumen.add_page();
+        let mut field = BitField::default();
+        field.place(&fumen.pages[0].piece.unwrap());
+        assert_eq!(BitField::from(&fumen.pages[1]), field);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_masks_rows() {
+        let mut rows = [0; 23];
+        rows[0] = 0xFFFF;
+        let field: BitField = serde_json::from_value(serde_json::json!(rows)).unwrap();
+        assert_eq!(field.rows()[0], BitField::FULL_ROW);
+        assert_eq!(serde_json::to_value(field).unwrap()[0], BitField::FULL_ROW);
+    }
+}
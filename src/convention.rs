@@ -0,0 +1,138 @@
+use crate::{Piece, PieceType, RotationState};
+
+/// A way of giving the position of a piece, as used by various tools.
+///
+/// [`Piece`] uses SRS true rotation centers with y going up from the bottom row of the field.
+/// Positions in other conventions can be converted to and from that.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PieceConvention {
+    /// SRS true rotation centers, y-up. This is what [`Piece`] uses.
+    Srs,
+    /// The centers fumen uses in its data, y-up. These are the same as SRS true rotation centers
+    /// except for some rotations of the S, Z, O and I pieces.
+    Fumen,
+    /// The positions used by solution-finder, which are the same as [`PieceConvention::Srs`].
+    Sfinder,
+    /// The positions used by the tetris-fumen JavaScript library, which are the same as
+    /// [`PieceConvention::Srs`].
+    TetrisFumen,
+    /// The positions used by TETR.IO: SRS true rotation centers with y going down from the top
+    /// row of its 40 row board.
+    Tetrio,
+    /// SRS true rotation centers with y going down from the top row of a board `height` rows
+    /// tall.
+    YDown { height: i32 }
+}
+
+impl PieceConvention {
+    /// Creates a piece from a position in this convention.
+    pub fn to_piece(self, kind: PieceType, rotation: RotationState, x: i32, y: i32) -> Piece {
+        let (dx, dy) = self.offset(kind, rotation);
+        let (x, y) = (x - dx, y - dy);
+        let y = match self.y_down_height() {
+            Some(height) => height - 1 - y,
+            None => y
+        };
+        Piece { kind, rotation, x, y }
+    }
+
+    /// The position of the piece in this convention.
+    pub fn position(self, piece: &Piece) -> (i32, i32) {
+        let (dx, dy) = self.offset(piece.kind, piece.rotation);
+        let y = match self.y_down_height() {
+            Some(height) => height - 1 - piece.y,
+            None => piece.y
+        };
+        (piece.x + dx, y + dy)
+    }
+
+    /// Converts a position in this convention to a position in another.
+    pub fn convert(
+        self, to: PieceConvention, kind: PieceType, rotation: RotationState, x: i32, y: i32
+    ) -> (i32, i32) {
+        to.position(&self.to_piece(kind, rotation, x, y))
+    }
+
+    fn y_down_height(self) -> Option<i32> {
+        match self {
+            PieceConvention::Tetrio => Some(40),
+            PieceConvention::YDown { height } => Some(height),
+            _ => None
+        }
+    }
+
+    /// How far this convention's position is from the SRS true rotation center, before flipping
+    /// y.
+    fn offset(self, kind: PieceType, rotation: RotationState) -> (i32, i32) {
+        if self != PieceConvention::Fumen {
+            return (0, 0);
+        }
+        match (kind, rotation) {
+            (PieceType::S, RotationState::East) => (1, 0),
+            (PieceType::Z, RotationState::West) => (-1, 0),
+            (PieceType::O, RotationState::West) => (-1, 1),
+            (PieceType::O, RotationState::South) => (-1, 0),
+            (PieceType::I, RotationState::South) => (-1, 0),
+            (PieceType::S, RotationState::North) => (0, 1),
+            (PieceType::Z, RotationState::North) => (0, 1),
+            (PieceType::O, RotationState::North) => (0, 1),
+            (PieceType::I, RotationState::West) => (0, 1),
+            _ => (0, 0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    const CONVENTIONS: [PieceConvention; 6] = [
+        PieceConvention::Srs,
+        PieceConvention::Fumen,
+        PieceConvention::Sfinder,
+        PieceConvention::TetrisFumen,
+        PieceConvention::Tetrio,
+        PieceConvention::YDown { height: 23 }
+    ];
+
+    #[test]
+    fn positions() {
+        let o = Piece { kind: PieceType::O, rotation: RotationState::West, x: 1, y: 0 };
+        assert_eq!(PieceConvention::Srs.position(&o), (1, 0));
+        assert_eq!(PieceConvention::Fumen.position(&o), (0, 1));
+        assert_eq!(PieceConvention::TetrisFumen.position(&o), (1, 0));
+        assert_eq!(PieceConvention::Tetrio.position(&o), (1, 39));
+        assert_eq!(PieceConvention::YDown { height: 23 }.position(&o), (1, 22));
+        assert_eq!(
+            PieceConvention::Fumen.convert(
+                PieceConvention::Tetrio, PieceType::S, RotationState::East, 5, 3
+            ),
+            (4, 36)
+        );
+
+        // fumen's centers are where it encodes pieces
+        let mut fumen = Fumen::default();
+        fumen.add_page().piece = Some(o);
+        assert_eq!(fumen.encode(), "v115@vhAbJJ");
+    }
+
+    #[test]
+    fn round_trip() {
+        for &convention in &CONVENTIONS {
+            for &kind in &[
+                PieceType::I, PieceType::L, PieceType::O, PieceType::Z,
+                PieceType::T, PieceType::J, PieceType::S
+            ] {
+                for &rotation in &[
+                    RotationState::North, RotationState::East,
+                    RotationState::South, RotationState::West
+                ] {
+                    let piece = Piece { kind, rotation, x: 4, y: 7 };
+                    let (x, y) = convention.position(&piece);
+                    assert_eq!(convention.to_piece(kind, rotation, x, y), piece);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::sync::Arc;
+
+use crate::CellColor;
+
+const CHUNK_ROWS: usize = 4;
+const CHUNKS: usize = 6;
+const HEIGHT: usize = 23;
+
+type Chunk = [[CellColor; 10]; CHUNK_ROWS];
+
+/// The 23 rows of a page's field, y-up.
+///
+/// This can be used like a `[[CellColor; 10]; 23]`. Rows are stored in shared chunks which are
+/// only copied when they are modified, so the pages of a fumen share the parts of the field that
+/// don't change between them.
+#[derive(Clone)]
+pub struct Field {
+    /// The rows, in chunks of 4. The last row of the last chunk is always empty.
+    chunks: [Arc<Chunk>; CHUNKS]
+}
+
+impl Field {
+    /// The row at height `y`, or `None` if it is above the field.
+    pub fn get(&self, y: usize) -> Option<&[CellColor; 10]> {
+        if y < HEIGHT {
+            Some(&self[y])
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over the rows from the bottom up.
+    pub fn iter(&self) -> Rows<'_> {
+        Rows { field: self, range: 0..HEIGHT }
+    }
+
+    /// Iterates mutably over the rows from the bottom up.
+    ///
+    /// This stops the rows it reaches from being shared with other pages.
+    pub fn iter_mut(&mut self) -> RowsMut<'_> {
+        RowsMut { chunks: self.chunks.iter_mut(), rows: [].iter_mut(), remaining: HEIGHT }
+    }
+
+    /// Copies the rows into an array.
+    pub fn to_array(&self) -> [[CellColor; 10]; 23] {
+        let mut rows = [[CellColor::Empty; 10]; 23];
+        for (row, from) in rows.iter_mut().zip(self.iter()) {
+            *row = *from;
+        }
+        rows
+    }
+
+    /// Sets a row, leaving it shared if it doesn't change.
+    pub(crate) fn set_row(&mut self, y: usize, row: [CellColor; 10]) {
+        if self[y] != row {
+            self[y] = row;
+        }
+    }
+
+    /// The number of chunks which are shared with `other`.
+    #[cfg(test)]
+    pub(crate) fn shared_chunks(&self, other: &Field) -> usize {
+        self.chunks.iter().zip(&other.chunks).filter(|(a, b)| Arc::ptr_eq(a, b)).count()
+    }
+}
+
+fn check_row(y: usize) {
+    if y >= HEIGHT {
+        panic!("index out of bounds: the len is {} but the index is {}", HEIGHT, y);
+    }
+}
+
+impl std::ops::Index<usize> for Field {
+    type Output = [CellColor; 10];
+
+    fn index(&self, y: usize) -> &[CellColor; 10] {
+        check_row(y);
+        &self.chunks[y / CHUNK_ROWS][y % CHUNK_ROWS]
+    }
+}
+
+impl std::ops::IndexMut<usize> for Field {
+    fn index_mut(&mut self, y: usize) -> &mut [CellColor; 10] {
+        check_row(y);
+        &mut Arc::make_mut(&mut self.chunks[y / CHUNK_ROWS])[y % CHUNK_ROWS]
+    }
+}
+
+impl<'a> IntoIterator for &'a Field {
+    type Item = &'a [CellColor; 10];
+    type IntoIter = Rows<'a>;
+
+    fn into_iter(self) -> Rows<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over the rows of a [`Field`], created by [`Field::iter`].
+#[derive(Clone, Debug)]
+pub struct Rows<'a> {
+    field: &'a Field,
+    range: std::ops::Range<usize>
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = &'a [CellColor; 10];
+
+    fn next(&mut self) -> Option<&'a [CellColor; 10]> {
+        self.range.next().map(|y| &self.field[y])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Rows<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back().map(|y| &self.field[y])
+    }
+}
+
+impl ExactSizeIterator for Rows<'_> {}
+
+impl<'a> IntoIterator for &'a mut Field {
+    type Item = &'a mut [CellColor; 10];
+    type IntoIter = RowsMut<'a>;
+
+    fn into_iter(self) -> RowsMut<'a> {
+        self.iter_mut()
+    }
+}
+
+/// Mutable iterator over the rows of a [`Field`], created by [`Field::iter_mut`].
+#[derive(Debug)]
+pub struct RowsMut<'a> {
+    chunks: std::slice::IterMut<'a, Arc<Chunk>>,
+    /// The rows left in the current chunk.
+    rows: std::slice::IterMut<'a, [CellColor; 10]>,
+    remaining: usize
+}
+
+impl<'a> Iterator for RowsMut<'a> {
+    type Item = &'a mut [CellColor; 10];
+
+    fn next(&mut self) -> Option<&'a mut [CellColor; 10]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        loop {
+            if let Some(row) = self.rows.next() {
+                self.remaining -= 1;
+                return Some(row);
+            }
+            self.rows = Arc::make_mut(self.chunks.next()?).iter_mut();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for RowsMut<'_> {}
+
+impl Default for Field {
+    fn default() -> Field {
+        let empty = Arc::new([[CellColor::Empty; 10]; CHUNK_ROWS]);
+        Field {
+            chunks: [
+                empty.clone(), empty.clone(), empty.clone(),
+                empty.clone(), empty.clone(), empty
+            ]
+        }
+    }
+}
+
+impl From<[[CellColor; 10]; 23]> for Field {
+    fn from(rows: [[CellColor; 10]; 23]) -> Field {
+        let mut field = Field::default();
+        for (y, &row) in rows.iter().enumerate() {
+            field.set_row(y, row);
+        }
+        field
+    }
+}
+
+impl From<&Field> for [[CellColor; 10]; 23] {
+    fn from(field: &Field) -> [[CellColor; 10]; 23] {
+        field.to_array()
+    }
+}
+
+impl PartialEq for Field {
+    fn eq(&self, other: &Field) -> bool {
+        self.chunks.iter().zip(&other.chunks).all(|(a, b)| Arc::ptr_eq(a, b) || a == b)
+    }
+}
+
+impl Eq for Field {}
+
+impl PartialEq<[[CellColor; 10]; 23]> for Field {
+    fn eq(&self, other: &[[CellColor; 10]; 23]) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl std::hash::Hash for Field {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        for row in self.iter() {
+            row.hash(state);
+        }
+    }
+}
+
+impl std::fmt::Debug for Field {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Field {
+    /// Serializes as a sequence of the 23 rows, from the bottom up.
+    fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Field {
+    fn deserialize<D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        <[[CellColor; 10]; 23]>::deserialize(de).map(Field::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn index_and_compare() {
+        let mut rows = [[CellColor::Empty; 10]; 23];
+        rows[0] = [CellColor::Grey; 10];
+        rows[22][9] = CellColor::T;
+        let mut field = Field::default();
+        field[0] = [CellColor::Grey; 10];
+        field[22][9] = CellColor::T;
+        assert_eq!(field, rows);
+        assert_eq!(field, Field::from(rows));
+        assert_eq!(field.to_array(), rows);
+        assert_eq!(field.iter().next_back(), Some(&rows[22]));
+        assert_eq!(field.iter().count(), 23);
+        assert_ne!(field, Field::default());
+        assert_eq!(format!("{:?}", field), format!("{:?}", rows));
+
+        for row in &mut field {
+            row[0] = CellColor::I;
+        }
+        assert!(field.iter().all(|row| row[0] == CellColor::I));
+        assert_eq!(field.iter_mut().count(), 23);
+    }
+
+    #[test]
+    #[should_panic]
+    fn out_of_bounds() {
+        let _ = Field::default()[23];
+    }
+
+    #[test]
+    fn sharing() {
+        let mut fumen = Fumen::default();
+        let page = fumen.add_page();
+        page.field[0] = [CellColor::Grey; 10];
+        page.field[0][0] = CellColor::Empty;
+        page.field[10] = [CellColor::Grey; 10];
+        page.field[10][0] = CellColor::Empty;
+        page.piece = Some(
+            Piece { kind: PieceType::O, rotation: RotationState::North, x: 4, y: 5 }
+        );
+        let page = fumen.add_page();
+        page.field[0][0] = CellColor::Grey;
+
+        let data = fumen.encode();
+        let decoded = Fumen::decode(&data).unwrap();
+        assert_eq!(decoded, fumen);
+        for fumen in &[fumen, decoded] {
+            // only the chunks touched by the piece and the cell change are copied
+            assert_eq!(fumen.pages[0].field.shared_chunks(&fumen.pages[1].field), 4);
+        }
+    }
+}
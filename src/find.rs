@@ -0,0 +1,201 @@
+use std::ops::Range;
+
+use crate::url::{find_data, is_data_char, percent_decode};
+use crate::{DecodeFumenError, DecodeOptions, Fumen, Host, Prefix};
+
+/// A fumen found by [`find_all`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FoundFumen {
+    /// The bytes of the text the fumen was found in, including the rest of the link if the data
+    /// was part of one.
+    pub span: Range<usize>,
+    /// The prefix the data started with.
+    pub prefix: Prefix,
+    /// The host the fumen was linked to, if it was part of a link to a known host.
+    pub host: Option<Host>,
+    /// The result of decoding the data. Error offsets are relative to the searched text.
+    pub result: Result<Fumen, DecodeFumenError>
+}
+
+/// Finds every fumen in free-form text, such as a chat message.
+///
+/// Fumens are found in the same way as [`Fumen::from_url`] does, so both links and bare data are
+/// found. Data which was wrapped onto following lines is joined back together as long as the
+/// joined data decodes successfully.
+pub fn find_all(text: &str) -> FindAll {
+    find_all_with_options(text, DecodeOptions::default())
+}
+
+/// Finds every fumen in free-form text, with limits on the resources used to decode each one.
+///
+/// This should be used for text from untrusted sources. The limits apply to the data of each
+/// fumen separately. See [`find_all`] and [`Fumen::decode_with_options`] for details.
+pub fn find_all_with_options(text: &str, options: DecodeOptions) -> FindAll {
+    let (decoded, origins) = percent_decode(text);
+    FindAll { decoded, origins, options, position: 0 }
+}
+
+/// Iterator over the fumens in some text, created by [`find_all`].
+#[derive(Clone, Debug)]
+pub struct FindAll {
+    /// The text with URL encoding undone.
+    decoded: String,
+    /// The offset into the original text of each byte of `decoded`.
+    origins: Vec<usize>,
+    options: DecodeOptions,
+    /// Where to continue searching `decoded` from.
+    position: usize
+}
+
+impl Iterator for FindAll {
+    type Item = FoundFumen;
+
+    fn next(&mut self) -> Option<FoundFumen> {
+        let text = &self.decoded[..];
+        let first = find_data(text, self.position)?;
+        let prefix = Prefix::parse(&text[first.start..]).unwrap();
+
+        // find the data on following lines that may be a continuation of this data
+        let mut lines = vec![first.clone()];
+        let body = first.start + Prefix::LENGTH;
+        let mut end = body + data_length(&text[body..]);
+        loop {
+            let rest = &text[end..];
+            let start = end + if rest.starts_with("\r\n") {
+                2
+            } else if rest.starts_with('\n') {
+                1
+            } else {
+                break
+            };
+            let length = data_length(&text[start..]);
+            if length == 0 || Prefix::parse(&text[start..]).is_some() {
+                break
+            }
+            end = start + length;
+            lines.push(start..start + text[start..end].trim_end_matches('?').len());
+        }
+
+        // try the longest continuation first, falling back to just the first line
+        let mut count = lines.len();
+        let (end, result) = loop {
+            let mut data = String::new();
+            let mut origins = vec![];
+            for line in &lines[..count] {
+                data.push_str(&text[line.clone()]);
+                origins.extend(line.clone());
+            }
+            let end = lines[count - 1].end;
+            origins.push(end);
+            let result = Fumen::decode_with_options(&data, self.options).map_err(|mut e| {
+                let offset = e.offset_mut();
+                *offset = self.origins[origins[*offset]];
+                e
+            });
+            if result.is_ok() || count == 1 {
+                break (end, result);
+            }
+            count -= 1;
+        };
+
+        // include the rest of the link, if there is one
+        let word_start = text[self.position..first.start]
+            .rfind(|c: char| c.is_whitespace() || "<([{\"'`".contains(c))
+            .map_or(self.position, |i| {
+                let i = self.position + i;
+                i + text[i..].chars().next().unwrap().len_utf8()
+            });
+        let word = &text[word_start..first.start];
+        let (start, host) = if word.contains("://") {
+            (word_start, Host::detect(word))
+        } else {
+            (first.start, None)
+        };
+
+        self.position = end;
+        Some(FoundFumen {
+            span: self.origins[start]..self.origins[end],
+            prefix,
+            host,
+            result
+        })
+    }
+}
+
+/// The length of the data characters at the start of `text`.
+fn data_length(text: &str) -> usize {
+    text.find(|c: char| !is_data_char(c)).unwrap_or(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn hello_world() -> Fumen {
+        let mut fumen = Fumen::default();
+        fumen.add_page().comment = Some("Hello World!".to_owned());
+        fumen
+    }
+
+    #[test]
+    fn find_in_text() {
+        let text = "try this: <http://fumen.zui.jp/?v115%40vhAAgWQAIoMDEvoo2AXXaDEkoA6A>, or this?\n\
+            ```\n\
+            v115@vhAAgWQAIoMDEv\n\
+            oo2AXXaDEkoA6A\n\
+            ```\n\
+            and then [this](https://harddrop.com/fumen/?m115@vhAAgWQA?IoMDEvoo2AXXaDE?) v115@vhAAgH?";
+        let found: Vec<_> = find_all(text).collect();
+        let view = Prefix { mode: ViewMode::View, version: Version::V115 };
+        assert_eq!(found, vec![
+            FoundFumen {
+                span: 11..67,
+                prefix: view,
+                host: Some(Host::FumenZui),
+                result: Ok(hello_world())
+            },
+            FoundFumen {
+                span: 83..117,
+                prefix: view,
+                host: None,
+                result: Ok(hello_world())
+            },
+            FoundFumen {
+                span: 138..195,
+                prefix: Prefix { mode: ViewMode::Mobile, version: Version::V115 },
+                host: Some(Host::HardDrop),
+                result: Err(DecodeFumenError::TruncatedComment { offset: 195, page: 0 })
+            },
+            FoundFumen {
+                span: 198..209,
+                prefix: view,
+                host: None,
+                result: Ok(Fumen::decode("v115@vhAAgH").unwrap())
+            },
+        ]);
+        assert_eq!(&text[found[1].span.clone()], "v115@vhAAgWQAIoMDEv\noo2AXXaDEkoA6A");
+    }
+
+    #[test]
+    fn wrapped_line_fallback() {
+        let found: Vec<_> = find_all("v115@vhAAgH\nhello").collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].span, 0..11);
+        assert_eq!(found[0].result, Fumen::decode("v115@vhAAgH"));
+        assert_eq!(find_all("nothing here, v115 or otherwise").count(), 0);
+        assert_eq!(find_all("Check this out, café is nice").count(), 0);
+        let found: Vec<_> = find_all("éé v115@vhAAgH ü").collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].span, 5..16);
+
+        let options = DecodeOptions { max_pages: Some(1), ..DecodeOptions::default() };
+        let found: Vec<_> = find_all_with_options("v115@vhAAgH and v115@vhBAgHAAA", options)
+            .map(|found| found.result.map(|fumen| fumen.pages.len()))
+            .collect();
+        assert_eq!(found, [
+            Ok(1),
+            Err(DecodeFumenError::LimitExceeded { offset: 27, page: 1, limit: Limit::Pages })
+        ]);
+    }
+}
@@ -0,0 +1,437 @@
+use crate::quiz::advance_comment;
+use crate::{CellColor, Fumen, Page, Piece, PieceType, RotationState};
+
+/// A page in the model of the tetris-fumen JavaScript library.
+///
+/// With the `serde` feature this has the same JSON shape as tetris-fumen's pages:
+/// `{ field, operation: { type, rotation, x, y }, flags: { lock, mirror, rise, colorize, quiz },
+/// comment, refs }`. Missing properties take the same defaults tetris-fumen uses when encoding.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct JsPage {
+    /// The field as tetris-fumen's `Field.str()` writes it: rows of `_IJLOSTZX` from the top down
+    /// separated by newlines, with the empty rows at the top left out and the garbage row last.
+    ///
+    /// `None` takes the field the previous page leaves behind.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub field: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub operation: Option<JsOperation>,
+    pub flags: JsFlags,
+    /// The comment the page shows, including ones inherited from earlier pages.
+    pub comment: String,
+    pub refs: JsRefs
+}
+
+/// The piece of a [`JsPage`].
+///
+/// The coordinates are the same as those of [`Piece`]: tetris-fumen also converts fumen's piece
+/// positions to SRS rotation centers, y-up.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JsOperation {
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub kind: PieceType,
+    pub rotation: JsRotation,
+    pub x: i32,
+    pub y: i32
+}
+
+/// tetris-fumen's names for rotation states.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum JsRotation {
+    Spawn,
+    Right,
+    Reverse,
+    Left
+}
+
+/// The flags of a [`JsPage`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct JsFlags {
+    pub lock: bool,
+    pub mirror: bool,
+    pub rise: bool,
+    /// The guideline flag of the fumen. Only the first page's is used.
+    pub colorize: bool,
+    /// Whether the comment is a quiz. This is ignored when converting to a [`Fumen`], since it
+    /// follows from the comment.
+    pub quiz: bool
+}
+
+/// The pages a [`JsPage`] takes things from.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct JsRefs {
+    /// If the page's field is the one the previous page leaves behind, the last page before it
+    /// whose field wasn't.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub field: Option<usize>,
+    /// The last page with a comment of its own, if this page inherits its comment.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub comment: Option<usize>
+}
+
+impl Default for JsFlags {
+    fn default() -> JsFlags {
+        JsFlags { lock: true, mirror: false, rise: false, colorize: true, quiz: false }
+    }
+}
+
+impl Fumen {
+    /// Converts to pages in the model of the tetris-fumen JavaScript library.
+    pub fn to_js_pages(&self) -> Vec<JsPage> {
+        let mut field_ref = 0;
+        let mut comment_ref = 0;
+        let mut previous: Option<Page> = None;
+        self.pages.iter().zip(self.comments()).enumerate().map(|(i, (page, comment))| {
+            let carried = previous.take().is_some_and(|p| {
+                p.field == page.field && p.garbage_row == page.garbage_row
+            });
+            if !carried {
+                field_ref = i;
+            }
+            if i == 0 || page.comment.is_some() {
+                comment_ref = i;
+            }
+            previous = Some(page.next_page());
+            JsPage {
+                field: Some(field_string(page)),
+                operation: page.piece.map(JsOperation::from),
+                flags: JsFlags {
+                    lock: page.lock,
+                    mirror: page.mirror,
+                    rise: page.rise,
+                    colorize: self.guideline,
+                    quiz: comment.starts_with("#Q=")
+                },
+                refs: JsRefs {
+                    field: Some(field_ref).filter(|_| carried),
+                    comment: Some(comment_ref).filter(|_| page.comment.is_none() && i != 0)
+                },
+                comment: comment.into_owned()
+            }
+        }).collect()
+    }
+
+    /// Converts from pages in the model of the tetris-fumen JavaScript library.
+    ///
+    /// A page's comment is only inherited if it is the same as the comment it would inherit and
+    /// either `refs.comment` is set or it is the first page.
+    pub fn from_js_pages(pages: &[JsPage]) -> Result<Fumen, FromJsPagesError> {
+        let mut fumen = Fumen {
+            guideline: pages.first().map_or(true, |p| p.flags.colorize),
+            ..Fumen::default()
+        };
+        let mut shown = String::new();
+        for (i, js) in pages.iter().enumerate() {
+            let mut page = match fumen.pages.last() {
+                Some(p) => {
+                    let inherited = advance_comment(&shown, p);
+                    if let Some(inherited) = inherited {
+                        shown = inherited;
+                    }
+                    p.next_page()
+                }
+                None => Page::default()
+            };
+            if let Some(ref field) = js.field {
+                parse_field(field, &mut page).ok_or(FromJsPagesError::InvalidField { page: i })?;
+            }
+            page.piece = match js.operation {
+                Some(operation) => Some(
+                    operation.to_piece().ok_or(FromJsPagesError::PieceOutOfBounds { page: i })?
+                ),
+                None => None
+            };
+            page.lock = js.flags.lock;
+            page.mirror = js.flags.mirror;
+            page.rise = js.flags.rise;
+            let inherits = i == 0 || js.refs.comment.is_some();
+            if !inherits || js.comment != shown {
+                page.comment = Some(js.comment.clone());
+                shown.clone_from(&js.comment);
+            }
+            fumen.pages.push(page);
+        }
+        Ok(fumen)
+    }
+}
+
+impl JsOperation {
+    /// The piece, or `None` if it doesn't lie inside of the field.
+    fn to_piece(self) -> Option<Piece> {
+        let piece = Piece {
+            kind: self.kind,
+            rotation: self.rotation.into(),
+            x: self.x,
+            y: self.y
+        };
+        Some(piece).filter(Piece::in_field)
+    }
+}
+
+impl From<Piece> for JsOperation {
+    fn from(piece: Piece) -> JsOperation {
+        JsOperation {
+            kind: piece.kind,
+            rotation: piece.rotation.into(),
+            x: piece.x,
+            y: piece.y
+        }
+    }
+}
+
+impl From<RotationState> for JsRotation {
+    fn from(rotation: RotationState) -> JsRotation {
+        match rotation {
+            RotationState::North => JsRotation::Spawn,
+            RotationState::East => JsRotation::Right,
+            RotationState::South => JsRotation::Reverse,
+            RotationState::West => JsRotation::Left
+        }
+    }
+}
+
+impl From<JsRotation> for RotationState {
+    fn from(rotation: JsRotation) -> RotationState {
+        match rotation {
+            JsRotation::Spawn => RotationState::North,
+            JsRotation::Right => RotationState::East,
+            JsRotation::Reverse => RotationState::South,
+            JsRotation::Left => RotationState::West
+        }
+    }
+}
+
+fn field_string(page: &Page) -> String {
+    let rows = page.field.iter().rev().skip_while(|row| row.iter().all(|&c| c == CellColor::Empty));
+    let mut field = String::new();
+    for row in rows.chain(std::iter::once(&page.garbage_row)) {
+        field.extend(row.iter().map(|&c| cell_char(c)));
+        field.push('\n');
+    }
+    field.pop();
+    field
+}
+
+/// Sets the field and garbage row of `page`, or returns `None` if `field` is invalid.
+fn parse_field(field: &str, page: &mut Page) -> Option<()> {
+    let mut lines = field.split('\n').rev();
+    page.garbage_row = parse_row(lines.next()?)?;
+    let mut y = 0;
+    for line in lines {
+        if y == 23 {
+            return None;
+        }
+        page.field.set_row(y, parse_row(line)?);
+        y += 1;
+    }
+    for y in y..23 {
+        page.field.set_row(y, [CellColor::Empty; 10]);
+    }
+    Some(())
+}
+
+fn parse_row(line: &str) -> Option<[CellColor; 10]> {
+    let mut row = [CellColor::Empty; 10];
+    let mut chars = line.chars();
+    for cell in &mut row {
+        *cell = cell_from_char(chars.next()?)?;
+    }
+    match chars.next() {
+        Some(_) => None,
+        None => Some(row)
+    }
+}
+
+fn cell_char(cell: CellColor) -> char {
+    match cell {
+        CellColor::Empty => '_',
+        CellColor::I => 'I',
+        CellColor::L => 'L',
+        CellColor::O => 'O',
+        CellColor::Z => 'Z',
+        CellColor::T => 'T',
+        CellColor::J => 'J',
+        CellColor::S => 'S',
+        CellColor::Grey => 'X'
+    }
+}
+
+fn cell_from_char(c: char) -> Option<CellColor> {
+    Some(match c {
+        '_' => CellColor::Empty,
+        'I' => CellColor::I,
+        'L' => CellColor::L,
+        'O' => CellColor::O,
+        'Z' => CellColor::Z,
+        'T' => CellColor::T,
+        'J' => CellColor::J,
+        'S' => CellColor::S,
+        'X' => CellColor::Grey,
+        _ => return None
+    })
+}
+
+/// The error type for [`Fumen::from_js_pages`].
+///
+/// Every variant records the index of the `page` with the problem.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum FromJsPagesError {
+    /// The field string is malformed or has too many rows.
+    InvalidField { page: usize },
+    /// Part of the piece lies outside of the field.
+    PieceOutOfBounds { page: usize }
+}
+
+impl std::fmt::Display for FromJsPagesError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FromJsPagesError::InvalidField { page } => {
+                write!(fmt, "invalid field on page {}", page + 1)
+            }
+            FromJsPagesError::PieceOutOfBounds { page } => {
+                write!(fmt, "piece is outside of the field on page {}", page + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FromJsPagesError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn sample() -> Fumen {
+        let mut fumen = Fumen { guideline: false, ..Fumen::default() };
+        let page = fumen.add_page();
+        page.field[0] = [CellColor::Grey; 10];
+        page.field[0][4] = CellColor::Empty;
+        page.garbage_row[0] = CellColor::Grey;
+        page.piece = Some(
+            Piece { kind: PieceType::I, rotation: RotationState::West, x: 4, y: 1 }
+        );
+        page.comment = Some("#Q=[](I)TS".to_owned());
+        fumen.add_page().piece = Some(
+            Piece { kind: PieceType::S, rotation: RotationState::North, x: 4, y: 0 }
+        );
+        let page = fumen.add_page();
+        page.lock = false;
+        page.rise = true;
+        page.mirror = true;
+        let page = fumen.add_page();
+        page.field[5][0] = CellColor::Grey;
+        page.comment = Some("done".to_owned());
+        fumen.add_page();
+        fumen
+    }
+
+    #[test]
+    fn to_js() {
+        let fumen = sample();
+        let pages = fumen.to_js_pages();
+        assert_eq!(pages.len(), 5);
+        assert_eq!(pages[0].field.as_deref(), Some("XXXX_XXXXX\nX_________"));
+        assert_eq!(
+            pages[0].operation,
+            Some(JsOperation { kind: PieceType::I, rotation: JsRotation::Left, x: 4, y: 1 })
+        );
+        assert_eq!(
+            pages[0].flags,
+            JsFlags { lock: true, mirror: false, rise: false, colorize: false, quiz: true }
+        );
+        assert_eq!(pages[0].refs, JsRefs::default());
+        assert_eq!(pages[1].comment, "#Q=[](T)S");
+        assert_eq!(
+            pages[1].field.as_deref(),
+            Some("____I_____\n____I_____\n____I_____\nX_________")
+        );
+        assert_eq!(pages[1].refs, JsRefs { field: Some(0), comment: Some(0) });
+        assert_eq!(
+            pages[3].field.as_deref(),
+            Some(concat!(
+                "X_________\n__________\n_____I____\n____SS____\n_____SS___\n",
+                "_________X\n__________"
+            ))
+        );
+        assert_eq!(pages[4].refs, JsRefs { field: Some(3), comment: Some(3) });
+        assert_eq!(pages[4].comment, "done");
+    }
+
+    #[test]
+    fn round_trip() {
+        let fumen = sample();
+        let decoded = Fumen::decode(&fumen.encode()).unwrap();
+        assert_eq!(Fumen::from_js_pages(&fumen.to_js_pages()), Ok(fumen.clone()));
+        assert_eq!(Fumen::from_js_pages(&decoded.to_js_pages()), Ok(decoded));
+
+        let mut pages = fumen.to_js_pages();
+        pages[4].field = None;
+        pages[4].comment = "edited".to_owned();
+        let converted = Fumen::from_js_pages(&pages).unwrap();
+        assert_eq!(converted.pages[4].field, fumen.pages[4].field);
+        assert_eq!(converted.pages[4].comment.as_deref(), Some("edited"));
+    }
+
+    #[test]
+    fn errors() {
+        let mut page = JsPage { field: Some("XXXXXXXXX".to_owned()), ..JsPage::default() };
+        assert_eq!(
+            Fumen::from_js_pages(&[JsPage::default(), page.clone()]),
+            Err(FromJsPagesError::InvalidField { page: 1 })
+        );
+        page.field = Some(vec!["__________"; 25].join("\n"));
+        assert_eq!(
+            Fumen::from_js_pages(&[page]),
+            Err(FromJsPagesError::InvalidField { page: 0 })
+        );
+        let page = JsPage {
+            operation: Some(
+                JsOperation { kind: PieceType::T, rotation: JsRotation::Spawn, x: 0, y: 0 }
+            ),
+            ..JsPage::default()
+        };
+        assert_eq!(
+            Fumen::from_js_pages(&[page]),
+            Err(FromJsPagesError::PieceOutOfBounds { page: 0 })
+        );
+        let page = JsPage {
+            operation: Some(JsOperation {
+                kind: PieceType::T, rotation: JsRotation::Spawn, x: i32::MAX, y: i32::MIN
+            }),
+            ..JsPage::default()
+        };
+        assert_eq!(
+            Fumen::from_js_pages(&[page]),
+            Err(FromJsPagesError::PieceOutOfBounds { page: 0 })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        let pages = sample().to_js_pages();
+        let json = serde_json::to_value(&pages).unwrap();
+        assert_eq!(
+            json[0]["operation"],
+            serde_json::json!({ "type": "I", "rotation": "left", "x": 4, "y": 1 })
+        );
+        assert_eq!(json[4]["refs"], serde_json::json!({ "field": 3, "comment": 3 }));
+        assert_eq!(json[0]["refs"], serde_json::json!({}));
+        assert!(json[2].get("operation").is_none());
+        assert_eq!(serde_json::from_value::<Vec<JsPage>>(json).unwrap(), pages);
+
+        let page: JsPage = serde_json::from_str(r#"{ "comment": "hi" }"#).unwrap();
+        assert_eq!(page, JsPage { comment: "hi".to_owned(), ..JsPage::default() });
+    }
+}
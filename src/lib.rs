@@ -1,15 +1,51 @@
-
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+use std::borrow::Cow;
+
+mod binary;
+mod bitfield;
+mod convention;
+mod field;
+mod find;
+mod js;
+mod lossless;
+mod movement;
+mod quiz;
+mod rotation;
+mod stream;
+#[cfg(feature = "serde")]
+pub mod structured;
+mod url;
+
+pub use binary::FromBytesError;
+pub use bitfield::BitField;
+pub use convention::PieceConvention;
+pub use field::{Field, Rows, RowsMut};
+pub use find::{find_all, find_all_with_options, FindAll, FoundFumen};
+pub use js::{FromJsPagesError, JsFlags, JsOperation, JsPage, JsRefs, JsRotation};
+pub use lossless::Encoding;
+use lossless::PageEncoding;
+pub use quiz::{ParseQuizError, Quiz};
+pub use rotation::{
+    Ars, Classic, KickTable, Kicks, RotationDirection, RotationSystem, RotationSystems, Srs, SrsPlus
+};
+pub use stream::{DecodeOptions, FumenReader, Limit};
+pub use url::Host;
+
+/// Fumens are compared and hashed by their pages and `guideline` flag, so a fumen decoded with
+/// [`Fumen::decode_lossless`] is equal to the same fumen decoded with [`Fumen::decode`].
+#[derive(Clone, Debug)]
 pub struct Fumen {
     pub pages: Vec<Page>,
-    pub guideline: bool
+    pub guideline: bool,
+    /// How the fumen was originally encoded, if it was decoded with [`Fumen::decode_lossless`].
+    pub encoding: Option<Encoding>
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Page {
     pub piece: Option<Piece>,
     /// y-up
-    pub field: [[CellColor; 10]; 23],
+    pub field: Field,
     pub garbage_row: [CellColor; 10],
     pub rise: bool,
     pub mirror: bool,
@@ -18,6 +54,7 @@ pub struct Page {
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PieceType {
     I = 1,
     L = 2,
@@ -29,6 +66,7 @@ pub enum PieceType {
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RotationState {
     South = 0,
     East = 1,
@@ -38,16 +76,20 @@ pub enum RotationState {
 
 
 /// Represents a tetromino piece using true rotation.
+///
+/// The position is that of the piece's SRS rotation center, which may be outside of the field.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Piece {
     pub kind: PieceType,
     pub rotation: RotationState,
-    pub x: u32,
+    pub x: i32,
     /// y-up
-    pub y: u32
+    pub y: i32
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CellColor {
     Empty = 0,
     I = 1,
@@ -72,230 +114,283 @@ const BASE64_CHARS: [u8; 64] = [
 
 impl Fumen {
     /// Encode as a fumen data string.
+    ///
+    /// Comments longer than fumen allows are truncated. Comments which are the same as the
+    /// comment the page would inherit (see [`Fumen::comments`]) are left out, so those pages
+    /// decode with a comment of `None` even though they show the same comment.
+    ///
+    /// If the fumen was decoded with [`Fumen::decode_lossless`], the original encoding is
+    /// reproduced for every page which still decodes to the same thing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a piece lies outside of the field. Use [`Fumen::try_encode`] to get an error
+    /// instead.
     pub fn encode(&self) -> String {
-        // we need a vec and not a string here since we need to go back and patch in the
-        // length of empty field sequences... and i don't want to do 2-pass encoding
-        let mut data = b"v115@".to_vec();
+        let mut data = String::new();
+        self.encode_into(&mut data).unwrap();
+        data
+    }
+
+    /// Encode as a fumen data string, writing it to `out` as it is produced.
+    ///
+    /// The data is never held in a string of its own, but encoding still allocates for each
+    /// page: the next page is created with [`Page::next_page`] to find the field it starts from,
+    /// advanced quiz comments are built as new strings, and the original comments of a fumen
+    /// decoded with [`Fumen::decode_lossless`] are unescaped to compare them. See
+    /// [`Fumen::encode`] for details.
+    pub fn encode_into(&self, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+        let encoding = self.encoding.as_ref();
+        out.write_str(encoding.map_or("v", |e| e.prefix.mode.as_str()))?;
+        out.write_str("115@")?;
+        let mut out = Writer {
+            out,
+            written: 0,
+            separators: encoding.map_or(&[], |e| &e.separators[..])
+        };
+
         let mut prev_field = [[CellColor::Empty; 10]; 24];
-        let mut empty_field = None;
+        // the number of following pages in the current empty field sequence
+        let mut empty_fields = 0;
         let mut first = true;
-        for page in &self.pages {
+        for (i, (page, inherited_comment)) in self.pages.iter()
+            .zip(self.inherited_comments())
+            .enumerate()
+        {
+            let original = encoding.and_then(|e| e.pages.get(i));
+
             // encode field
             let deltas = fumen_field_delta(prev_field, page.fumen_field());
-            if deltas == [[8; 10]; 24] {
-                // do special-case unchanged field stuff
-                if let Some((ref mut index, ref mut count)) = empty_field {
-                    // count empty fields
-                    *count += 1;
-                    if *count == 63 {
-                        data[*index] = BASE64_CHARS[*count];
-                        empty_field = None;
+            if empty_fields > 0 {
+                // the field was already covered by the empty field sequence
+                empty_fields -= 1;
+            } else {
+                let original_runs = original.and_then(|e| e.field.as_deref())
+                    .filter(|runs| lossless::runs_match(runs, &deltas));
+                if let Some(runs) = original_runs {
+                    for &num in runs {
+                        out.number(num as usize, 2)?;
                     }
-                } else {
+                } else if deltas == [[8; 10]; 24] {
                     // new empty field encoding
-                    data.push(b'v');
-                    data.push(b'h');
-                    empty_field = Some((data.len(), 0));
-                    data.push(0);
-                }
-            } else {
-                // finalize the empty field sequence
-                if let Some((index, count)) = empty_field {
-                    data[index] = BASE64_CHARS[count];
-                    empty_field = None;
-                }
-                // do run-length encoding of deltas
-                let mut prev = deltas[0][0];
-                let mut count = 0;
-                for y in 0..24 {
-                    for x in 0..10 {
-                        if deltas[y][x] == prev {
-                            count += 1;
-                        } else {
-                            let num = prev * 240 + count - 1;
-                            data.push(BASE64_CHARS[num & 0x3F]);
-                            data.push(BASE64_CHARS[num >> 6 & 0x3F]);
-                            prev = deltas[y][x];
-                            count = 1;
+                    out.number(EMPTY_FIELD, 2)?;
+                } else {
+                    // do run-length encoding of deltas
+                    let mut prev = deltas[0][0];
+                    let mut count = 0;
+                    #[allow(clippy::needless_range_loop)]
+                    for y in 0..24 {
+                        for x in 0..10 {
+                            if deltas[y][x] == prev {
+                                count += 1;
+                            } else {
+                                out.number(prev * 240 + count - 1, 2)?;
+                                prev = deltas[y][x];
+                                count = 1;
+                            }
                         }
                     }
+                    out.number(prev * 240 + count - 1, 2)?;
+                }
+                if deltas == [[8; 10]; 24] && original_runs.map_or(true, |runs| runs.len() == 1) {
+                    // look ahead to find how many pages reuse this field
+                    empty_fields = self.empty_field_sequence(i);
+                    // cut off data can have a sequence which runs past the last page
+                    let reaches_end = i + empty_fields + 1 == self.pages.len();
+                    let count = original.map(|e| e.empty_fields)
+                        .filter(|&count| reaches_end && count >= empty_fields)
+                        .unwrap_or(empty_fields);
+                    out.number(count, 1)?;
                 }
-                let num = prev * 240 + count - 1;
-                data.push(BASE64_CHARS[num & 0x3F]);
-                data.push(BASE64_CHARS[num >> 6 & 0x3F]);
             }
 
-            let page_flags = page.fumen_number() as usize + if first {
-                first = false;
+            let original_comment = original.and_then(|e| e.comment.as_deref())
+                .filter(|&c| page.comment.as_deref() == Some(&js_unescape(c)));
+            let comment = page.comment.as_deref()
+                .filter(|&c| original_comment.is_none() && c != inherited_comment);
+            let has_comment = original_comment.is_some() || comment.is_some();
+
+            let page_flags = page.fumen_number(has_comment) as usize + if first {
                 self.guideline as usize * 240 * 128
             } else { 0 };
-            data.push(BASE64_CHARS[page_flags & 0x3F]);
-            data.push(BASE64_CHARS[page_flags >> 6 & 0x3F]);
-            data.push(BASE64_CHARS[page_flags >> 12 & 0x3F]);
-
-            if let Some(ref comment) = page.comment {
-                let mut encoded = js_escape(comment);
-                encoded.truncate(4095);
-                data.push(BASE64_CHARS[encoded.len() & 0x3F]);
-                data.push(BASE64_CHARS[encoded.len() >> 6 & 0x3F]);
-
-                for c in encoded.chunks(4) {
-                    let mut v = 0;
-                    for &c in c.iter().rev() {
-                        v *= 96;
-                        v += c as usize - 0x20;
-                    }
-                    for _ in 0..5 {
-                        data.push(BASE64_CHARS[v & 0x3F]);
-                        v >>= 6;
-                    }
-                }
+            let page_flags = original.map(|e| e.action)
+                .filter(|&action| lossless::action_matches(action, page_flags, first))
+                .unwrap_or(page_flags);
+            first = false;
+            out.number(page_flags, 3)?;
+
+            if let Some(escaped) = original_comment {
+                out.comment(escaped.len(), escaped.bytes())?;
+            } else if let Some(comment) = comment {
+                out.comment(escaped_length(comment, MAX_COMMENT_LENGTH), js_escape(comment))?;
             }
 
             // this handles piece locking, line clear, mirror, and rise rules
             prev_field = page.next_page().fumen_field();
         }
 
-        // finalize the empty field sequence
-        if let Some((index, count)) = empty_field {
-            data[index] = BASE64_CHARS[count];
+        out.finish()
+    }
+
+    /// Encode as a fumen data string, writing it to `writer` as it is produced.
+    ///
+    /// The data is written in many small pieces, so `writer` should usually be buffered. This
+    /// allocates in the same way as [`Fumen::encode_into`]. See [`Fumen::encode`] for details.
+    pub fn encode_to_writer(&self, writer: impl std::io::Write) -> std::io::Result<()> {
+        struct Adapter<W> {
+            writer: W,
+            error: std::io::Result<()>
+        }
+
+        impl<W: std::io::Write> std::fmt::Write for Adapter<W> {
+            fn write_str(&mut self, s: &str) -> std::fmt::Result {
+                self.error = self.writer.write_all(s.as_bytes());
+                self.error.as_ref().map_err(|_| std::fmt::Error).copied()
+            }
         }
 
-        String::from_utf8(data).unwrap()
+        let mut adapter = Adapter { writer, error: Ok(()) };
+        match self.encode_into(&mut adapter) {
+            Ok(()) => Ok(()),
+            Err(_) => adapter.error
+        }
     }
 
-    /// Decodes a fumen data string.
-    pub fn decode(data: &str) -> Result<Fumen, DecodeFumenError> {
-        Fumen::decode_opt(data).ok_or(DecodeFumenError)
+    /// The number of pages after `index` which can reuse its field in an empty field sequence.
+    fn empty_field_sequence(&self, index: usize) -> usize {
+        let encoding = self.encoding.as_ref();
+        self.pages[index..].windows(2).enumerate().take(63).take_while(|&(i, pages)| {
+            // pages decoded with their own field keep it
+            let original = encoding.and_then(|e| e.pages.get(index + i + 1));
+            original.map_or(true, |e| e.field.is_none()) &&
+                pages[0].next_page().fumen_field() == pages[1].fumen_field()
+        }).count()
     }
 
-    fn decode_opt(data: &str) -> Option<Fumen> {
-        if data.chars().take(5).collect::<String>() != "v115@" {
-            return None;
-        }
-        let mut iter = data[5..].chars().filter(|&c| c != '?').map(from_base64).peekable();
-        let mut fumen = Fumen::default();
-        let mut empty_fields = 0;
-        while iter.peek().is_some() {
-            let page = fumen.add_page();
-            if empty_fields == 0 {
-                // decode field spec
-                let mut delta = [[0; 10]; 24];
-                let mut x = 0;
-                let mut y = 0;
-                while y != 24 {
-                    let number = iter.next()?? + 64 * iter.next()??;
-                    let value = number / 240;
-                    let repeats = number % 240 + 1;
-                    for _ in 0..repeats {
-                        if y == 24 {
-                            return None;
-                        }
-                        delta[y][x] = value;
-                        x += 1;
-                        if x == 10 {
-                            y += 1;
-                            x = 0;
-                        }
-                    }
+    /// Encode as a fumen data string, checking that the result will decode to the same pages,
+    /// apart from comments which are the same as the inherited comment and so decode as `None`.
+    ///
+    /// Unlike [`Fumen::encode`], this doesn't silently truncate long comments, and it also rejects
+    /// pieces which overlap filled cells of the field.
+    pub fn try_encode(&self) -> Result<String, EncodeFumenError> {
+        for (index, page) in self.pages.iter().enumerate() {
+            if let Some(piece) = page.piece {
+                if !piece.in_field() {
+                    return Err(EncodeFumenError::PieceOutOfBounds { page: index });
                 }
-                if delta == [[8; 10]; 24] {
-                    empty_fields = iter.next()??;
+                if piece.overlaps(&BitField::from(&page.field)) {
+                    return Err(EncodeFumenError::PieceOverlapsField { page: index });
                 }
-                for y in 0..23 {
-                    for x in 0..10 {
-                        let value = delta[y][x] + page.field[22-y][x] as usize - 8;
-                        page.field[22-y][x] = decode_cell_color(value)?;
-                    }
-                }
-                for x in 0..10 {
-                    let value = delta[23][x] + page.garbage_row[x] as usize - 8;
-                    page.garbage_row[x] = decode_cell_color(value)?;
+            }
+            if let Some(ref comment) = page.comment {
+                if js_escape(comment).count() > MAX_COMMENT_LENGTH {
+                    return Err(EncodeFumenError::CommentTooLong { page: index });
                 }
-            } else {
-                empty_fields -= 1;
             }
+        }
+        Ok(self.encode())
+    }
 
-            // decode page data
-            let number = iter.next()?? + iter.next()?? * 64 + iter.next()?? * 64*64;
-            let piece_type = number % 8;
-            let piece_rot = number / 8 % 4;
-            let piece_pos = number / 32 % 240;
-
-            page.piece = if piece_type == 0 { None } else {
-                let kind = match piece_type {
-                    1 => PieceType::I,
-                    2 => PieceType::L,
-                    3 => PieceType::O,
-                    4 => PieceType::Z,
-                    5 => PieceType::T,
-                    6 => PieceType::J,
-                    7 => PieceType::S,
-                    _ => unreachable!()
-                };
-                let rotation = match piece_rot {
-                    0 => RotationState::South,
-                    1 => RotationState::East,
-                    2 => RotationState::North,
-                    3 => RotationState::West,
-                    _ => unreachable!()
-                };
-                let x = piece_pos as u32 % 10;
-                let y = 22 - piece_pos as u32 / 10;
-                Some(Piece {
-                    kind, rotation,
-                    // we need to convert fumen centers to SRS true rotation centers
-                    x: match (kind, rotation) {
-                        (PieceType::S, RotationState::East) => x - 1,
-                        (PieceType::Z, RotationState::West) => x + 1,
-                        (PieceType::O, RotationState::West) => x + 1,
-                        (PieceType::O, RotationState::South) => x + 1,
-                        (PieceType::I, RotationState::South) => x + 1,
-                        _ => x
-                    },
-                    y: match (kind, rotation) {
-                        (PieceType::S, RotationState::North) => y - 1,
-                        (PieceType::Z, RotationState::North) => y - 1,
-                        (PieceType::O, RotationState::North) => y - 1,
-                        (PieceType::O, RotationState::West) => y - 1,
-                        (PieceType::I, RotationState::West) => y - 1,
-                        _ => y
-                    }
-                })
-            };
+    /// Encode as a fumen data string which opens fumen in the given view mode.
+    ///
+    /// See [`Fumen::encode`] for details.
+    pub fn encode_with_prefix(&self, mode: ViewMode) -> String {
+        let mut data = self.encode();
+        data.replace_range(..1, mode.as_str());
+        data
+    }
 
-            let flags = number / 32 / 240;
-            page.rise = flags & 0b1 != 0;
-            page.mirror = flags & 0b10 != 0;
-            let guideline = flags & 0b100 != 0;
-            let comment = flags & 0b1000 != 0;
-            page.lock = flags & 0b10000 == 0;
-
-            if comment {
-                let mut length = iter.next()?? + iter.next()?? * 64;
-                let mut escaped = String::new();
-                while length > 0 {
-                    let mut number = iter.next()?? + iter.next()?? * 64 + iter.next()?? * 64 * 64
-                        + iter.next()?? * 64 * 64 * 64 + iter.next()?? * 64 * 64 * 64 * 64;
-                    for _ in 0..length.min(4) {
-                        escaped.push(std::char::from_u32(number as u32 % 96 + 0x20)?);
-                        length -= 1;
-                        number /= 96;
-                    }
-                }
-                page.comment = Some(js_unescape(&escaped));
-            }
+    /// Decodes a fumen data string.
+    ///
+    /// All view modes of both the current `v115` format and the legacy `v110` format are
+    /// supported. Since `v110` fumens have a shorter field, their fields are placed at the bottom
+    /// of the 23 row field.
+    ///
+    /// This does not panic on any input, and every piece in the result lies inside the field.
+    pub fn decode(data: &str) -> Result<Fumen, DecodeFumenError> {
+        Fumen::decode_with_prefix(data).map(|(fumen, _)| fumen)
+    }
 
-            if fumen.pages.len() == 1 {
-                fumen.guideline = guideline;
+    /// Decodes a fumen data string, also returning the prefix the data started with.
+    ///
+    /// See [`Fumen::decode`] for details.
+    pub fn decode_with_prefix(data: &str) -> Result<(Fumen, Prefix), DecodeFumenError> {
+        Fumen::decode_inner(data, DecodeOptions::default(), false)
+    }
+
+    /// Decodes a fumen data string with limits on the resources used.
+    ///
+    /// This should be used when decoding untrusted data. See [`Fumen::decode`] for details.
+    pub fn decode_with_options(
+        data: &str, options: DecodeOptions
+    ) -> Result<Fumen, DecodeFumenError> {
+        Fumen::decode_inner(data, options, false).map(|(fumen, _)| fumen)
+    }
+
+    /// Decodes fumen data, keeping the details of how it was encoded in [`Fumen::encoding`] if
+    /// `lossless` is set.
+    fn decode_inner(
+        data: &str, options: DecodeOptions, lossless: bool
+    ) -> Result<(Fumen, Prefix), DecodeFumenError> {
+        let mut reader = FumenReader::with_options(data, options)?;
+        let prefix = reader.prefix();
+        // the encoder can only reproduce the current version
+        let lossless = lossless && prefix.version == Version::V115;
+        if lossless {
+            reader.track_separators();
+        }
+        let mut fumen = Fumen::default();
+        let mut encoding = vec![];
+        loop {
+            let mut page_encoding = PageEncoding {
+                field: None,
+                empty_fields: 0,
+                action: 0,
+                comment: None
+            };
+            let record = if lossless { Some(&mut page_encoding) } else { None };
+            let page = match reader.read_page(record) {
+                Some(page) => page?,
+                None => break
+            };
+            fumen.pages.push(page);
+            if lossless {
+                encoding.push(page_encoding);
             }
         }
-        Some(fumen)
+        fumen.guideline = reader.guideline();
+        if let Some(separators) = reader.into_separators() {
+            fumen.encoding = Some(Encoding { prefix, separators, pages: encoding });
+        }
+        Ok((fumen, prefix))
+    }
+
+    /// Checks every page for inconsistencies, returning all of the problems found.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        self.pages.iter().enumerate().flat_map(|(page, p)| {
+            p.validate().into_iter().map(move |problem| Diagnostic { page, problem })
+        }).collect()
+    }
+
+    /// Resolves the comment fumen displays on each page.
+    ///
+    /// A page without a comment shows the comment of the page before it, and the first page
+    /// shows an empty comment if it doesn't have one. If the previous page shows a [`Quiz`] and
+    /// locks a piece, the quiz is advanced past that piece.
+    pub fn comments(&self) -> Vec<Cow<'_, str>> {
+        self.pages.iter().zip(self.inherited_comments()).map(|(page, inherited)| {
+            match page.comment {
+                Some(ref comment) => Cow::Borrowed(&comment[..]),
+                None => inherited
+            }
+        }).collect()
     }
 
     /// Create a new page, in the same way as creating a new page in fumen does.
     ///
     /// This will apply the piece locking, line clear, rise, and mirror rules just like fumen does.
+    /// The new page has no comment of its own, so if the previous page shows a [`Quiz`] and locks
+    /// a piece, the new page shows the advanced quiz (see [`Fumen::comments`]).
     pub fn add_page(&mut self) -> &mut Page {
         self.pages.push(match self.pages.last() {
             Some(p) => p.next_page(),
@@ -305,6 +400,23 @@ impl Fumen {
     }
 }
 
+/// The maximum length of an escaped comment.
+const MAX_COMMENT_LENGTH: usize = 4095;
+
+/// The length of the longest prefix of the escaped string which is at most `max` bytes long and
+/// doesn't split an escape sequence or a surrogate pair.
+fn escaped_length(s: &str, max: usize) -> usize {
+    let mut length = 0;
+    for c in s.chars() {
+        let (_, len) = js_escape_char(c);
+        if length + len > max {
+            break;
+        }
+        length += len;
+    }
+    length
+}
+
 fn fumen_field_delta(
     from: [[CellColor; 10]; 24], to: [[CellColor; 10]; 24]
 ) -> [[usize; 10]; 24] {
@@ -317,6 +429,41 @@ fn fumen_field_delta(
     deltas
 }
 
+/// Decodes the piece part of a page's piece and flags number.
+fn decode_piece(number: usize, field_height: usize) -> Result<Option<Piece>, ()> {
+    let cells = (field_height + 1) * 10;
+    let piece_type = number % 8;
+    let piece_rot = number / 8 % 4;
+    let piece_pos = number / 32 % cells;
+    if piece_type == 0 {
+        return Ok(None);
+    }
+    let kind = match piece_type {
+        1 => PieceType::I,
+        2 => PieceType::L,
+        3 => PieceType::O,
+        4 => PieceType::Z,
+        5 => PieceType::T,
+        6 => PieceType::J,
+        7 => PieceType::S,
+        _ => unreachable!()
+    };
+    let rotation = match piece_rot {
+        0 => RotationState::South,
+        1 => RotationState::East,
+        2 => RotationState::North,
+        3 => RotationState::West,
+        _ => unreachable!()
+    };
+    let x = (piece_pos % 10) as i32;
+    let y = (field_height - 1) as i32 - (piece_pos / 10) as i32;
+    let piece = PieceConvention::Fumen.to_piece(kind, rotation, x, y);
+    if !piece.in_field() {
+        return Err(());
+    }
+    Ok(Some(piece))
+}
+
 fn decode_cell_color(value: usize) -> Option<CellColor> {
     Some(match value {
         0 => CellColor::Empty,
@@ -343,12 +490,322 @@ fn from_base64(c: char) -> Option<usize> {
     })
 }
 
+/// The view fumen opens a link in, given by the first character of the data.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ViewMode {
+    /// The viewer, `v`.
+    View,
+    /// The mobile viewer, `m`.
+    Mobile,
+    /// The data view, `d`.
+    Data,
+    /// The editor, `D`.
+    Editor
+}
+
+impl ViewMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            ViewMode::View => "v",
+            ViewMode::Mobile => "m",
+            ViewMode::Data => "d",
+            ViewMode::Editor => "D"
+        }
+    }
+}
+
+/// The version of the fumen data format.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Version {
+    /// The legacy format with a 21 row field.
+    V110,
+    /// The current format with a 23 row field.
+    V115
+}
+
+impl Version {
+    fn field_height(self) -> usize {
+        match self {
+            Version::V110 => 21,
+            Version::V115 => 23
+        }
+    }
+}
+
+/// The prefix of fumen data, such as `v115@`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Prefix {
+    pub mode: ViewMode,
+    pub version: Version
+}
+
+impl Prefix {
+    /// The length of a prefix in bytes.
+    const LENGTH: usize = 5;
+
+    /// Parses the prefix at the start of `data`.
+    fn parse(data: &str) -> Option<Prefix> {
+        let prefix = data.as_bytes().get(..Prefix::LENGTH)?;
+        let mode = match prefix[0] {
+            b'v' => ViewMode::View,
+            b'm' => ViewMode::Mobile,
+            b'd' => ViewMode::Data,
+            b'D' => ViewMode::Editor,
+            _ => return None
+        };
+        let version = match &prefix[1..] {
+            b"115@" => Version::V115,
+            b"110@" => Version::V110,
+            _ => return None
+        };
+        Some(Prefix { mode, version })
+    }
+}
+
+impl std::fmt::Display for Prefix {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let version = match self.version {
+            Version::V110 => "110",
+            Version::V115 => "115"
+        };
+        write!(fmt, "{}{}@", self.mode.as_str(), version)
+    }
+}
+
+fn truncated_field(offset: usize, page: usize) -> DecodeFumenError {
+    DecodeFumenError::TruncatedField { offset, page }
+}
+
+fn truncated_page(offset: usize, page: usize) -> DecodeFumenError {
+    DecodeFumenError::TruncatedPage { offset, page }
+}
+
+fn truncated_comment(offset: usize, page: usize) -> DecodeFumenError {
+    DecodeFumenError::TruncatedComment { offset, page }
+}
+
+/// Reads base64 digits out of fumen data, keeping track of where we are for error reporting.
+#[derive(Clone, Debug)]
+struct Reader<'a> {
+    data: &'a str,
+    offset: usize,
+    page: usize,
+    /// The number of rows in the field, not including the garbage row.
+    field_height: usize,
+    /// Where `?` separators were found, if we're keeping track of them.
+    separators: Option<Vec<usize>>
+}
+
+impl Reader<'_> {
+    /// The number of cells in the field, including the garbage row.
+    fn cells(&self) -> usize {
+        (self.field_height + 1) * 10
+    }
+
+    fn skip_separators(&mut self) {
+        while self.data[self.offset..].starts_with('?') {
+            if let Some(ref mut separators) = self.separators {
+                separators.push(self.offset - Prefix::LENGTH - separators.len());
+            }
+            self.offset += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.offset == self.data.len()
+    }
+
+    fn digit(
+        &mut self, truncated: fn(usize, usize) -> DecodeFumenError
+    ) -> Result<usize, DecodeFumenError> {
+        self.skip_separators();
+        let c = match self.data[self.offset..].chars().next() {
+            Some(c) => c,
+            None => return Err(truncated(self.offset, self.page))
+        };
+        let value = from_base64(c).ok_or(DecodeFumenError::InvalidCharacter {
+            offset: self.offset, page: self.page, found: c
+        })?;
+        self.offset += c.len_utf8();
+        Ok(value)
+    }
+
+    /// Reads a little-endian number made of `digits` base64 digits.
+    fn number(
+        &mut self, digits: u32, truncated: fn(usize, usize) -> DecodeFumenError
+    ) -> Result<usize, DecodeFumenError> {
+        let mut number = 0;
+        for i in 0..digits {
+            number += self.digit(truncated)? << (6 * i);
+        }
+        Ok(number)
+    }
+
+    /// Decodes a field specification into `page`, returning the number of following pages which
+    /// reuse the field unchanged. The raw runs are pushed to `runs` if it is given.
+    fn field(
+        &mut self, page: &mut Page, mut runs: Option<&mut Vec<u16>>
+    ) -> Result<usize, DecodeFumenError> {
+        let cells = self.cells();
+        let mut index = 0;
+        let mut unchanged = false;
+        while index != cells {
+            let run_start = self.offset;
+            let number = self.number(2, truncated_field)?;
+            if let Some(ref mut runs) = runs {
+                runs.push(number as u16);
+            }
+            let value = number / cells;
+            let repeats = number % cells + 1;
+            if index + repeats > cells {
+                return Err(DecodeFumenError::FieldOverflow { offset: run_start, page: self.page });
+            }
+            unchanged = repeats == cells && value == 8;
+            // leave unchanged rows shared with the previous page
+            let changed = if value == 8 { 0 } else { repeats };
+            for i in index..index + changed {
+                let (x, y) = (i % 10, i / 10);
+                let cell = if y == self.field_height {
+                    &mut page.garbage_row[x]
+                } else {
+                    &mut page.field[self.field_height - 1 - y][x]
+                };
+                *cell = (*cell as usize + value).checked_sub(8)
+                    .and_then(decode_cell_color)
+                    .ok_or(DecodeFumenError::InvalidCellColor {
+                        offset: run_start, page: self.page
+                    })?;
+            }
+            index += repeats;
+        }
+        if unchanged {
+            self.number(1, truncated_field)
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Reads a comment, returning it still escaped.
+    fn comment(&mut self) -> Result<String, DecodeFumenError> {
+        let mut length = self.number(2, truncated_comment)?;
+        let mut escaped = String::new();
+        while length > 0 {
+            let start = self.offset;
+            let mut number = self.number(5, truncated_comment)?;
+            for _ in 0..length.min(4) {
+                let c = number % 96 + 0x20;
+                if c == 0x7F {
+                    return Err(DecodeFumenError::InvalidCommentCharacter {
+                        offset: start, page: self.page
+                    });
+                }
+                escaped.push(c as u8 as char);
+                length -= 1;
+                number /= 96;
+            }
+            if number != 0 {
+                return Err(DecodeFumenError::InvalidCommentCharacter {
+                    offset: start, page: self.page
+                });
+            }
+        }
+        Ok(escaped)
+    }
+}
+
+/// The field specification of a field which is the same as the previous one.
+const EMPTY_FIELD: usize = 8 * 240 + 239;
+
+/// Writes base64 digits of fumen data, putting back `?` separators from a lossless decode.
+struct Writer<'a, W> {
+    out: &'a mut W,
+    /// The number of digits written so far.
+    written: usize,
+    /// Where the remaining separators go.
+    separators: &'a [usize]
+}
+
+impl<W: std::fmt::Write> Writer<'_, W> {
+    fn separators(&mut self, until: usize) -> std::fmt::Result {
+        while let Some((&offset, rest)) = self.separators.split_first() {
+            if offset > until {
+                break;
+            }
+            self.out.write_char('?')?;
+            self.separators = rest;
+        }
+        Ok(())
+    }
+
+    fn digit(&mut self, value: usize) -> std::fmt::Result {
+        self.separators(self.written)?;
+        self.out.write_char(BASE64_CHARS[value & 0x3F] as char)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Writes a little-endian number made of `digits` base64 digits.
+    fn number(&mut self, mut number: usize, digits: u32) -> std::fmt::Result {
+        for _ in 0..digits {
+            self.digit(number)?;
+            number >>= 6;
+        }
+        Ok(())
+    }
+
+    /// Writes a comment made of the first `length` of the escaped bytes.
+    fn comment(&mut self, length: usize, escaped: impl Iterator<Item=u8>) -> std::fmt::Result {
+        self.number(length, 2)?;
+        let mut escaped = escaped.take(length).peekable();
+        while escaped.peek().is_some() {
+            let mut v = 0;
+            let mut scale = 1;
+            for c in escaped.by_ref().take(4) {
+                v += (c as usize - 0x20) * scale;
+                scale *= 96;
+            }
+            self.number(v, 5)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the separators at the end of the data.
+    fn finish(&mut self) -> std::fmt::Result {
+        self.separators(self.written)
+    }
+}
+
 impl Page {
-    fn fumen_number(&self) -> u32 {
+    /// Checks the page for inconsistencies, returning all of the problems found.
+    pub fn validate(&self) -> Vec<PageProblem> {
+        let mut problems = vec![];
+        if let Some(piece) = self.piece {
+            let field = BitField::from(&self.field);
+            if !piece.in_field() {
+                problems.push(PageProblem::PieceOutOfBounds);
+            } else if piece.overlaps(&field) {
+                problems.push(PageProblem::PieceOverlapsField);
+            } else if self.lock && !piece.supported(&field) {
+                problems.push(PageProblem::FloatingPiece);
+            }
+        }
+        for (x, &cell) in self.garbage_row.iter().enumerate() {
+            if cell != CellColor::Empty && cell != CellColor::Grey {
+                problems.push(PageProblem::NonGreyGarbage { x });
+            }
+        }
+        problems
+    }
+
+    fn fumen_number(&self, comment: bool) -> u32 {
         self.piece.map(|p| p.fumen_number()).unwrap_or(0) + 240 * 32 * (
             self.rise as u32 +
             2 * self.mirror as u32 +
-            8 * self.comment.is_some() as u32 +
+            8 * comment as u32 +
             16 * !self.lock as u32
         )
     }
@@ -365,54 +822,85 @@ impl Page {
     /// Create a page from this page in the same way as fumen does.
     ///
     /// This will apply the piece locking, line clear, rise, and mirror rules just like fumen does.
+    /// Cells of a locked piece which lie outside of the field are dropped.
     pub fn next_page(&self) -> Page {
-        let mut field = self.field;
+        self.next_page_detailed().0
+    }
+
+    /// Create a page from this page in the same way as [`Page::next_page`], also reporting what
+    /// happened to the field.
+    pub fn next_page_detailed(&self) -> (Page, Transition) {
+        // rows which don't change stay shared with this page
+        let mut field = self.field.clone();
+        let mut bits = BitField::from(&field);
+        let mut transition = Transition {
+            cleared_rows: 0,
+            lines_cleared: 0,
+            perfect_clear: false,
+            overlapped: false,
+            row_map: [None; 23]
+        };
+        for (y, new) in transition.row_map.iter_mut().enumerate() {
+            *new = Some(y);
+        }
 
         // do piece placement
         if let Some(piece) = self.piece {
             if self.lock {
-                for &(x, y) in &piece.cells() {
-                    field[y as usize][x as usize] = piece.kind.into();
+                transition.overlapped = piece.overlaps(&bits);
+                // cells outside of the field are dropped, like in BitField::place
+                for &(x, y) in piece.checked_cells().iter().flatten() {
+                    if (0..10).contains(&x) && (0..23).contains(&y) {
+                        field[y as usize][x as usize] = piece.kind.into();
+                    }
                 }
+                bits.place(&piece);
             }
         }
 
         // do line clear rule
         if self.lock {
+            transition.cleared_rows = bits.clear_lines();
             let mut y = 0;
             for i in 0..23 {
-                let mut cleared = true;
-                for x in 0..10 {
-                    if field[i][x] == CellColor::Empty {
-                        cleared = false;
-                    }
-                }
-                if !cleared {
-                    field[y] = field[i];
+                if transition.cleared_rows & 1 << i != 0 {
+                    transition.row_map[i] = None;
+                } else {
+                    let row = field[i];
+                    field.set_row(y, row);
+                    transition.row_map[i] = Some(y);
                     y += 1;
                 }
             }
             for i in y..23 {
-                field[i] = [CellColor::Empty; 10];
+                field.set_row(i, [CellColor::Empty; 10]);
             }
+            transition.lines_cleared = transition.cleared_rows.count_ones();
+            transition.perfect_clear = transition.lines_cleared != 0 && bits.is_empty();
         }
 
         // do "rise" rule
         if self.rise {
             for i in (1..23).rev() {
-                field[i] = field[i-1];
+                let row = field[i-1];
+                field.set_row(i, row);
+            }
+            field.set_row(0, self.garbage_row);
+            for new in &mut transition.row_map {
+                *new = new.map(|y| y + 1).filter(|&y| y < 23);
             }
-            field[0] = self.garbage_row;
         }
 
         // do "mirror" rule
         if self.mirror {
-            for row in &mut field {
+            for y in 0..23 {
+                let mut row = field[y];
                 row.reverse();
+                field.set_row(y, row);
             }
         }
 
-        Page {
+        let page = Page {
             piece: if self.lock { None } else { self.piece },
             comment: None,
             rise: false,
@@ -424,10 +912,28 @@ impl Page {
             } else {
                 self.garbage_row
             }
-        }
+        };
+        (page, transition)
     }
 }
 
+/// What happened to the field when moving to the next page, from [`Page::next_page_detailed`].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transition {
+    /// The rows which were cleared, with bit `y` set if row `y` of the original field was cleared.
+    pub cleared_rows: u32,
+    /// The number of rows which were cleared.
+    pub lines_cleared: u32,
+    /// Whether lines were cleared and that left the field empty, before the garbage row rises.
+    pub perfect_clear: bool,
+    /// Whether the locked piece overlapped filled cells of the field.
+    pub overlapped: bool,
+    /// Where each row of the original field is in the new field, or `None` if it was cleared or
+    /// pushed off the top by the garbage row.
+    pub row_map: [Option<usize>; 23]
+}
+
 impl Piece {
     fn fumen_number(&self) -> u32 {
         self.kind as u32 +
@@ -436,29 +942,117 @@ impl Piece {
     }
 
     fn fumen_pos(&self) -> u32 {
-        // Convert true SRS piece centers to fumen's system
-        let x = match (self.kind, self.rotation) {
-            (PieceType::S, RotationState::East) => self.x + 1,
-            (PieceType::Z, RotationState::West) => self.x - 1,
-            (PieceType::O, RotationState::West) => self.x - 1,
-            (PieceType::O, RotationState::South) => self.x - 1,
-            (PieceType::I, RotationState::South) => self.x - 1,
-            _ => self.x
-        };
-        let y = match (self.kind, self.rotation) {
-            (PieceType::S, RotationState::North) => self.y + 1,
-            (PieceType::Z, RotationState::North) => self.y + 1,
-            (PieceType::O, RotationState::North) => self.y + 1,
-            (PieceType::O, RotationState::West) => self.y + 1,
-            (PieceType::I, RotationState::West) => self.y + 1,
-            _ => self.y
+        assert!(self.in_field(), "piece {:?} is outside of the field", self);
+        let (x, y) = PieceConvention::Fumen.position(self);
+        (x + (22 - y) * 10) as u32
+    }
+
+    /// Whether every cell of the piece lies inside the 10x23 field.
+    pub fn in_field(&self) -> bool {
+        self.checked_cells().is_some_and(|cells| {
+            cells.iter().all(|&(x, y)| (0..10).contains(&x) && (0..23).contains(&y))
+        })
+    }
+
+    /// Whether any cell of the piece is on a filled cell of `field`.
+    fn overlaps(&self, field: &BitField) -> bool {
+        self.checked_cells().is_some_and(|cells| cells.iter().any(|&(x, y)| {
+            (0..10).contains(&x) && (0..23).contains(&y) && field.occupied(x, y)
+        }))
+    }
+
+    /// Whether the piece is resting on the floor or on a filled cell of `field`.
+    fn supported(&self, field: &BitField) -> bool {
+        self.checked_cells().is_some_and(|cells| cells.iter().any(|&(x, y)| {
+            y == 0 || y > 0 && (0..10).contains(&x) && field.occupied(x, y - 1)
+        }))
+    }
+
+    /// The cells of the piece, which may lie outside of the field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the coordinates of a cell don't fit in an `i32`.
+    pub fn cells(&self) -> [(i32, i32); 4] {
+        self.checked_cells().expect("piece coordinates overflow")
+    }
+
+    /// The cells of the piece, or `None` if the coordinates of a cell don't fit in an `i32`.
+    pub(crate) fn checked_cells(&self) -> Option<[(i32, i32); 4]> {
+        let mut cells = self.kind.shape(self.rotation);
+        for (x, y) in &mut cells {
+            *x = x.checked_add(self.x)?;
+            *y = y.checked_add(self.y)?;
+        }
+        Some(cells)
+    }
+
+    /// The piece moved by `dx` columns and `dy` rows into `rotation`, or `None` if its
+    /// coordinates don't fit in an `i32`.
+    pub(crate) fn moved(&self, rotation: RotationState, (dx, dy): (i32, i32)) -> Option<Piece> {
+        Some(Piece { rotation, x: self.x.checked_add(dx)?, y: self.y.checked_add(dy)?, ..*self })
+    }
+
+    /// The bottom left and top right cells of the smallest rectangle containing the piece.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same way as [`Piece::cells`].
+    pub fn bounding_box(&self) -> ((i32, i32), (i32, i32)) {
+        let (bottom_left, top_right) = self.kind.bounding_box(self.rotation);
+        let offset = |(x, y): (i32, i32)| {
+            x.checked_add(self.x).zip(y.checked_add(self.y)).expect("piece coordinates overflow")
         };
+        (offset(bottom_left), offset(top_right))
+    }
 
-        x + (22 - y) * 10
+    /// The piece in the first of North, East, South and West which has the same cells.
+    ///
+    /// S, Z and I pieces have two rotation states for each set of cells, and O pieces have four,
+    /// so this can be used to compare placements regardless of how the piece got there.
+    pub fn canonical(&self) -> Piece {
+        let mut cells = self.cells();
+        cells.sort_unstable();
+        for &rotation in &[
+            RotationState::North, RotationState::East, RotationState::South, RotationState::West
+        ] {
+            let mut shape = self.kind.shape(rotation);
+            shape.sort_unstable();
+            let (x, y) = (cells[0].0 - shape[0].0, cells[0].1 - shape[0].1);
+            if shape.iter().zip(&cells).all(|(&(dx, dy), &cell)| (x + dx, y + dy) == cell) {
+                return Piece { kind: self.kind, rotation, x, y };
+            }
+        }
+        unreachable!()
     }
+}
 
-    fn cells(&self) -> [(i32, i32); 4] {
-        let mut cells = match self.kind {
+impl RotationState {
+    /// The state after rotating clockwise.
+    pub fn cw(self) -> RotationState {
+        match self {
+            RotationState::North => RotationState::East,
+            RotationState::East => RotationState::South,
+            RotationState::South => RotationState::West,
+            RotationState::West => RotationState::North
+        }
+    }
+
+    /// The state after rotating counter-clockwise.
+    pub fn ccw(self) -> RotationState {
+        self.cw().cw().cw()
+    }
+
+    /// The state after rotating 180 degrees.
+    pub fn flip(self) -> RotationState {
+        self.cw().cw()
+    }
+}
+
+impl PieceType {
+    /// The cells of the piece relative to its SRS rotation center, y-up.
+    pub fn shape(self, rotation: RotationState) -> [(i32, i32); 4] {
+        let mut cells = match self {
             PieceType::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
             PieceType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
             PieceType::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
@@ -469,7 +1063,7 @@ impl Piece {
         };
 
         for (x, y) in &mut cells {
-            match self.rotation {
+            match rotation {
                 RotationState::North => {}
                 RotationState::East => {
                     std::mem::swap(x, y);
@@ -484,29 +1078,50 @@ impl Piece {
                     *x = -*x;
                 }
             }
-
-            *x += self.x as i32;
-            *y += self.y as i32;
         }
 
         cells
     }
+
+    /// The bottom left and top right corners of [`PieceType::shape`]'s bounding box.
+    pub fn bounding_box(self, rotation: RotationState) -> ((i32, i32), (i32, i32)) {
+        let cells = self.shape(rotation);
+        let min = |f: fn(&(i32, i32)) -> i32| cells.iter().map(f).min().unwrap();
+        let max = |f: fn(&(i32, i32)) -> i32| cells.iter().map(f).max().unwrap();
+        ((min(|c| c.0), min(|c| c.1)), (max(|c| c.0), max(|c| c.1)))
+    }
 }
 
 impl Default for Fumen {
     fn default() -> Self {
         Fumen {
             pages: vec![],
-            guideline: true
+            guideline: true,
+            encoding: None
         }
     }
 }
 
+impl PartialEq for Fumen {
+    fn eq(&self, other: &Fumen) -> bool {
+        self.pages == other.pages && self.guideline == other.guideline
+    }
+}
+
+impl Eq for Fumen {}
+
+impl std::hash::Hash for Fumen {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.pages.hash(state);
+        self.guideline.hash(state);
+    }
+}
+
 impl Default for Page {
     fn default() -> Self {
         Page {
             piece: None,
-            field: [[CellColor::Empty; 10]; 23],
+            field: Field::default(),
             garbage_row: [CellColor::Empty; 10],
             rise: false,
             mirror: false,
@@ -518,7 +1133,14 @@ impl Default for Page {
 
 #[cfg(feature = "serde")]
 impl serde::Serialize for Fumen {
+    /// Serializes as the string [`Fumen::encode`] produces, failing if a piece lies outside of
+    /// the field.
     fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        let outside = self.pages.iter()
+            .position(|p| p.piece.is_some_and(|piece| !piece.in_field()));
+        if let Some(page) = outside {
+            return Err(serde::ser::Error::custom(EncodeFumenError::PieceOutOfBounds { page }));
+        }
         ser.serialize_str(&self.encode())
     }
 }
@@ -554,35 +1176,47 @@ impl From<PieceType> for CellColor {
     }
 }
 
-fn js_escape(s: &str) -> Vec<u8> {
+fn js_escape(s: &str) -> impl Iterator<Item=u8> + '_ {
+    s.chars().flat_map(|c| {
+        let (buf, len) = js_escape_char(c);
+        (0..len).map(move |i| buf[i])
+    })
+}
+
+/// Escapes a single character, returning a buffer holding the escaped bytes and their count.
+fn js_escape_char(c: char) -> ([u8; 12], usize) {
     const HEX_DIGITS: [u8; 16] = [
         b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7',
         b'8', b'9', b'A', b'B', b'C', b'D', b'E', b'F'
     ];
 
-    let mut result = Vec::new();
-    for c in s.chars() {
-        match c {
-            'a' ..= 'z' | 'A' ..= 'Z' | '0' ..= '9' |
-            '@' | '*' | '_' | '+' | '-' | '.' | '/' => result.push(c as u8),
-            '\u{0}' ..= '\u{FF}' => {
-                result.push(b'%');
-                result.push(HEX_DIGITS[(c as usize) >> 4 & 0xF]);
-                result.push(HEX_DIGITS[(c as usize) >> 0 & 0xF]);
-            }
-            _ => {
-                let mut buf = [0; 2];
-                for &mut c in c.encode_utf16(&mut buf) {
-                    result.extend_from_slice(b"%u");
-                    result.push(HEX_DIGITS[(c as usize) >> 12 & 0xF]);
-                    result.push(HEX_DIGITS[(c as usize) >> 8 & 0xF]);
-                    result.push(HEX_DIGITS[(c as usize) >> 4 & 0xF]);
-                    result.push(HEX_DIGITS[(c as usize) >> 0 & 0xF]);
-                }
+    let mut result = [0; 12];
+    match c {
+        'a' ..= 'z' | 'A' ..= 'Z' | '0' ..= '9' |
+        '@' | '*' | '_' | '+' | '-' | '.' | '/' => {
+            result[0] = c as u8;
+            (result, 1)
+        }
+        '\u{0}' ..= '\u{FF}' => {
+            result[0] = b'%';
+            result[1] = HEX_DIGITS[(c as usize) >> 4 & 0xF];
+            result[2] = HEX_DIGITS[(c as usize) & 0xF];
+            (result, 3)
+        }
+        _ => {
+            let mut buf = [0; 2];
+            let mut len = 0;
+            for &mut c in c.encode_utf16(&mut buf) {
+                result[len..len + 2].copy_from_slice(b"%u");
+                result[len + 2] = HEX_DIGITS[(c as usize) >> 12 & 0xF];
+                result[len + 3] = HEX_DIGITS[(c as usize) >> 8 & 0xF];
+                result[len + 4] = HEX_DIGITS[(c as usize) >> 4 & 0xF];
+                result[len + 5] = HEX_DIGITS[(c as usize) & 0xF];
+                len += 6;
             }
+            (result, len)
         }
     }
-    result
 }
 
 fn js_unescape(s: &str) -> String {
@@ -616,17 +1250,208 @@ fn js_unescape(s: &str) -> String {
     String::from_utf16_lossy(&result_utf16)
 }
 
+/// The reason a fumen data string could not be decoded.
+///
+/// Every variant records the byte `offset` into the input at which the problem was found and the
+/// index of the `page` that was being decoded.
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
-pub struct DecodeFumenError;
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum DecodeFumenError {
+    /// The data does not start with a supported version prefix such as `v115@`.
+    UnsupportedVersion { offset: usize, page: usize },
+    /// A character outside of fumen's base64 alphabet was found.
+    InvalidCharacter { offset: usize, page: usize, found: char },
+    /// The data ended in the middle of a field specification.
+    TruncatedField { offset: usize, page: usize },
+    /// A run of cells extends past the end of the field.
+    FieldOverflow { offset: usize, page: usize },
+    /// Applying a run of cell deltas produced a color that doesn't exist.
+    InvalidCellColor { offset: usize, page: usize },
+    /// The piece is positioned outside of the field.
+    InvalidPiecePosition { offset: usize, page: usize },
+    /// The data ended in the middle of a page's piece and flags.
+    TruncatedPage { offset: usize, page: usize },
+    /// The data ended in the middle of a comment.
+    TruncatedComment { offset: usize, page: usize },
+    /// A comment contains a character which cannot appear in escaped comment text.
+    InvalidCommentCharacter { offset: usize, page: usize },
+    /// Decoding would exceed one of the limits set in [`DecodeOptions`].
+    LimitExceeded { offset: usize, page: usize, limit: Limit }
+}
+
+impl DecodeFumenError {
+    /// The byte offset into the input at which the problem was found.
+    pub fn offset(&self) -> usize {
+        match *self {
+            DecodeFumenError::UnsupportedVersion { offset, .. } => offset,
+            DecodeFumenError::InvalidCharacter { offset, .. } => offset,
+            DecodeFumenError::TruncatedField { offset, .. } => offset,
+            DecodeFumenError::FieldOverflow { offset, .. } => offset,
+            DecodeFumenError::InvalidCellColor { offset, .. } => offset,
+            DecodeFumenError::InvalidPiecePosition { offset, .. } => offset,
+            DecodeFumenError::TruncatedPage { offset, .. } => offset,
+            DecodeFumenError::TruncatedComment { offset, .. } => offset,
+            DecodeFumenError::InvalidCommentCharacter { offset, .. } => offset,
+            DecodeFumenError::LimitExceeded { offset, .. } => offset
+        }
+    }
+
+    fn offset_mut(&mut self) -> &mut usize {
+        match self {
+            DecodeFumenError::UnsupportedVersion { offset, .. } => offset,
+            DecodeFumenError::InvalidCharacter { offset, .. } => offset,
+            DecodeFumenError::TruncatedField { offset, .. } => offset,
+            DecodeFumenError::FieldOverflow { offset, .. } => offset,
+            DecodeFumenError::InvalidCellColor { offset, .. } => offset,
+            DecodeFumenError::InvalidPiecePosition { offset, .. } => offset,
+            DecodeFumenError::TruncatedPage { offset, .. } => offset,
+            DecodeFumenError::TruncatedComment { offset, .. } => offset,
+            DecodeFumenError::InvalidCommentCharacter { offset, .. } => offset,
+            DecodeFumenError::LimitExceeded { offset, .. } => offset
+        }
+    }
+
+    /// The index of the page that was being decoded when the problem was found.
+    pub fn page(&self) -> usize {
+        match *self {
+            DecodeFumenError::UnsupportedVersion { page, .. } => page,
+            DecodeFumenError::InvalidCharacter { page, .. } => page,
+            DecodeFumenError::TruncatedField { page, .. } => page,
+            DecodeFumenError::FieldOverflow { page, .. } => page,
+            DecodeFumenError::InvalidCellColor { page, .. } => page,
+            DecodeFumenError::InvalidPiecePosition { page, .. } => page,
+            DecodeFumenError::TruncatedPage { page, .. } => page,
+            DecodeFumenError::TruncatedComment { page, .. } => page,
+            DecodeFumenError::InvalidCommentCharacter { page, .. } => page,
+            DecodeFumenError::LimitExceeded { page, .. } => page
+        }
+    }
+}
 
 impl std::fmt::Display for DecodeFumenError {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(fmt, "the string does not contain valid fumen data")
+        match self {
+            DecodeFumenError::UnsupportedVersion { .. } => {
+                return write!(fmt, "the string does not start with a supported fumen version")
+            }
+            DecodeFumenError::InvalidCharacter { found, .. } => {
+                write!(fmt, "invalid character {:?}", found)?
+            }
+            DecodeFumenError::TruncatedField { .. } => write!(fmt, "field data is truncated")?,
+            DecodeFumenError::FieldOverflow { .. } => {
+                write!(fmt, "field data extends past the end of the field")?
+            }
+            DecodeFumenError::InvalidCellColor { .. } => write!(fmt, "invalid cell color")?,
+            DecodeFumenError::InvalidPiecePosition { .. } => {
+                write!(fmt, "piece is outside of the field")?
+            }
+            DecodeFumenError::TruncatedPage { .. } => write!(fmt, "page data is truncated")?,
+            DecodeFumenError::TruncatedComment { .. } => write!(fmt, "comment is truncated")?,
+            DecodeFumenError::InvalidCommentCharacter { .. } => {
+                write!(fmt, "invalid character in comment")?
+            }
+            DecodeFumenError::LimitExceeded { limit: Limit::Pages, .. } => {
+                write!(fmt, "too many pages")?
+            }
+            DecodeFumenError::LimitExceeded { limit: Limit::CommentBytes, .. } => {
+                write!(fmt, "too much comment text")?
+            }
+            DecodeFumenError::LimitExceeded { limit: Limit::InputLength, .. } => {
+                write!(fmt, "data is too long")?
+            }
+        }
+        write!(fmt, " at offset {} (page {})", self.offset(), self.page() + 1)
     }
 }
 
 impl std::error::Error for DecodeFumenError {}
 
+/// The reason a fumen could not be encoded.
+///
+/// Every variant records the index of the `page` with the problem.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum EncodeFumenError {
+    /// The page's comment is longer than fumen allows once escaped.
+    CommentTooLong { page: usize },
+    /// Part of the page's piece lies outside of the field.
+    PieceOutOfBounds { page: usize },
+    /// The page's piece overlaps filled cells of the field.
+    PieceOverlapsField { page: usize }
+}
+
+impl EncodeFumenError {
+    /// The index of the page with the problem.
+    pub fn page(&self) -> usize {
+        match *self {
+            EncodeFumenError::CommentTooLong { page } => page,
+            EncodeFumenError::PieceOutOfBounds { page } => page,
+            EncodeFumenError::PieceOverlapsField { page } => page
+        }
+    }
+}
+
+impl std::fmt::Display for EncodeFumenError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EncodeFumenError::CommentTooLong { .. } => write!(fmt, "comment is too long")?,
+            EncodeFumenError::PieceOutOfBounds { .. } => {
+                write!(fmt, "piece is outside of the field")?
+            }
+            EncodeFumenError::PieceOverlapsField { .. } => {
+                write!(fmt, "piece overlaps the field")?
+            }
+        }
+        write!(fmt, " on page {}", self.page() + 1)
+    }
+}
+
+impl std::error::Error for EncodeFumenError {}
+
+/// A problem found by [`Page::validate`].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum PageProblem {
+    /// Part of the piece lies outside of the field.
+    PieceOutOfBounds,
+    /// The piece overlaps filled cells of the field.
+    PieceOverlapsField,
+    /// The piece locks without resting on the floor or on a filled cell.
+    FloatingPiece,
+    /// The garbage row contains a color other than grey in column `x`.
+    NonGreyGarbage { x: usize }
+}
+
+impl std::fmt::Display for PageProblem {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PageProblem::PieceOutOfBounds => write!(fmt, "piece is outside of the field"),
+            PageProblem::PieceOverlapsField => write!(fmt, "piece overlaps the field"),
+            PageProblem::FloatingPiece => write!(fmt, "piece locks in mid-air"),
+            PageProblem::NonGreyGarbage { x } => {
+                write!(fmt, "garbage row has a non-grey cell in column {}", x + 1)
+            }
+        }
+    }
+}
+
+/// A problem found on a page by [`Fumen::validate`].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    pub page: usize,
+    pub problem: PageProblem
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "page {}: {}", self.page + 1, self.problem)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -702,6 +1527,94 @@ mod tests {
         ), Ok(fumen));
     }
 
+    #[test]
+    fn piece_geometry() {
+        assert_eq!(
+            PieceType::T.shape(RotationState::East),
+            [(0, 1), (0, 0), (0, -1), (1, 0)]
+        );
+        assert_eq!(PieceType::I.bounding_box(RotationState::West), ((0, -1), (0, 2)));
+        let piece = Piece { kind: PieceType::L, rotation: RotationState::South, x: 0, y: 0 };
+        assert_eq!(piece.cells(), [(1, 0), (0, 0), (-1, 0), (-1, -1)]);
+        assert_eq!(piece.bounding_box(), ((-1, -1), (1, 0)));
+        assert!(!piece.in_field());
+        assert_eq!(piece.canonical(), piece);
+
+        let piece = |kind, rotation, x, y| Piece { kind, rotation, x, y };
+        for &(from, to) in &[
+            (
+                piece(PieceType::S, RotationState::South, 4, 1),
+                piece(PieceType::S, RotationState::North, 4, 0)
+            ),
+            (
+                piece(PieceType::Z, RotationState::West, 5, 3),
+                piece(PieceType::Z, RotationState::East, 4, 3)
+            ),
+            (
+                piece(PieceType::I, RotationState::West, 0, 1),
+                piece(PieceType::I, RotationState::East, 0, 2)
+            ),
+            (
+                piece(PieceType::I, RotationState::South, -1, 5),
+                piece(PieceType::I, RotationState::North, -2, 5)
+            ),
+            (
+                piece(PieceType::O, RotationState::South, 1, 1),
+                piece(PieceType::O, RotationState::North, 0, 0)
+            ),
+            (
+                piece(PieceType::O, RotationState::East, 0, 1),
+                piece(PieceType::O, RotationState::North, 0, 0)
+            ),
+            (
+                piece(PieceType::O, RotationState::West, 1, 0),
+                piece(PieceType::O, RotationState::North, 0, 0)
+            )
+        ] {
+            assert_eq!(from.canonical(), to);
+            assert_eq!(to.canonical(), to);
+            let mut cells = from.cells();
+            let mut canonical = to.cells();
+            cells.sort_unstable();
+            canonical.sort_unstable();
+            assert_eq!(cells, canonical);
+        }
+    }
+
+    #[test]
+    fn extreme_coordinates() {
+        let far = Piece { kind: PieceType::I, rotation: RotationState::North, x: i32::MAX, y: 0 };
+        let low = Piece { x: 0, y: i32::MIN, ..far };
+        let mut fumen = Fumen::default();
+        for &piece in &[far, low] {
+            assert!(!piece.in_field());
+            let mut page = Page { piece: Some(piece), ..Page::default() };
+            assert_eq!(page.validate(), vec![PageProblem::PieceOutOfBounds]);
+            assert!(page.collides(&piece));
+            assert!(BitField::from(&page).collides(&piece));
+            assert_eq!(page.shift_right(&piece), None);
+            assert_eq!(page.soft_drop(&piece), None);
+            assert_eq!(page.rotate_cw(&piece), None);
+            page.lock = false;
+            fumen.pages.push(page);
+        }
+        assert_eq!(fumen.try_encode(), Err(EncodeFumenError::PieceOutOfBounds { page: 0 }));
+        fumen.pages[0].lock = true;
+        assert_eq!(fumen.pages[0].next_page(), Page::default());
+
+        // locking a piece partly outside of the field keeps the cells inside of it
+        let mut fumen = Fumen::default();
+        fumen.add_page().piece = Some(
+            Piece { kind: PieceType::T, rotation: RotationState::North, x: 0, y: 0 }
+        );
+        let page = fumen.add_page();
+        assert_eq!(page.field[0][..2], [CellColor::T; 2]);
+        assert_eq!(page.field[1][0], CellColor::T);
+        assert_eq!(fumen.to_js_pages().len(), 2);
+        // from_bytes only accepts pieces inside of the field, like decode
+        assert!(Fumen::from_bytes(&fumen.to_bytes()).is_err());
+    }
+
     #[test]
     fn fumen_field() {
         let mut page = Page::default();
@@ -725,6 +1638,26 @@ mod tests {
         assert_eq!(fumen_field_delta(empty, page.fumen_field()), deltas);
     }
 
+    #[test]
+    fn encode_into_writers() {
+        let mut fumen = Fumen::default();
+        for _ in 0..130 {
+            fumen.add_page();
+        }
+        fumen.pages[100].comment = Some("Hello World!".to_owned());
+        let data = fumen.encode();
+        assert_eq!(data.matches("vh/").count(), 2);
+        assert_eq!(Fumen::decode(&data), Ok(fumen.clone()));
+
+        let mut text = "fumen: ".to_owned();
+        fumen.encode_into(&mut text).unwrap();
+        assert_eq!(text, format!("fumen: {}", data));
+        let mut bytes = vec![];
+        fumen.encode_to_writer(&mut bytes).unwrap();
+        assert_eq!(bytes, data.as_bytes());
+        assert!(fumen.encode_to_writer(&mut [0; 100][..]).is_err());
+    }
+
     #[test]
     fn simple_field() {
         let mut fumen = Fumen::default();
@@ -765,6 +1698,56 @@ mod tests {
         assert_eq!(Fumen::decode("v115@bhJ8JeAgHvhAAAA"), Ok(fumen));
     }
 
+    #[test]
+    fn detailed_transition() {
+        let mut page = Page::default();
+        for y in 0..3 {
+            page.field[y] = [CellColor::Grey; 10];
+            page.field[y][0] = CellColor::Empty;
+        }
+        page.field[1][5] = CellColor::Empty;
+        page.field[3][9] = CellColor::T;
+        page.piece = Some(Piece { kind: PieceType::I, rotation: RotationState::East, x: 0, y: 2 });
+        let (next, transition) = page.next_page_detailed();
+        assert_eq!(next, page.next_page());
+        assert_eq!(transition.cleared_rows, 0b101);
+        assert_eq!(transition.lines_cleared, 2);
+        assert!(!transition.perfect_clear && !transition.overlapped);
+        assert_eq!(transition.row_map[..5], [None, Some(0), None, Some(1), Some(2)]);
+        assert_eq!(next.field[1][9], CellColor::T);
+
+        let mut page = Page { rise: true, ..Page::default() };
+        page.field[0] = [CellColor::Grey; 10];
+        page.field[0][6..].copy_from_slice(&[CellColor::Empty; 4]);
+        page.piece = Some(Piece { kind: PieceType::I, rotation: RotationState::North, x: 7, y: 0 });
+        let (next, transition) = page.clone().next_page_detailed();
+        assert!(transition.perfect_clear);
+        assert!(next.field.iter().all(|row| row == &[CellColor::Empty; 10]));
+        assert_eq!(transition.row_map[..2], [None, Some(1)]);
+        assert_eq!(transition.row_map[22], Some(22));
+        let mut garbage = page.clone();
+        garbage.garbage_row = [CellColor::Grey; 10];
+        garbage.garbage_row[0] = CellColor::Empty;
+        let (next, transition) = garbage.next_page_detailed();
+        assert!(transition.perfect_clear);
+        assert_eq!(next.field[0], garbage.garbage_row);
+
+        page.lock = false;
+        page.piece.as_mut().unwrap().y = 1;
+        let (_, transition) = page.next_page_detailed();
+        assert_eq!(transition.lines_cleared, 0);
+        assert!(!transition.overlapped);
+        assert_eq!(transition.row_map[..2], [Some(1), Some(2)]);
+        assert_eq!(transition.row_map[22], None);
+
+        let page = Page {
+            piece: Some(Piece { kind: PieceType::O, rotation: RotationState::North, x: 0, y: 0 }),
+            field: page.field,
+            ..Page::default()
+        };
+        assert!(page.next_page_detailed().1.overlapped);
+    }
+
     #[test]
     fn rise() {
         let mut fumen = Fumen::default();
@@ -804,6 +1787,24 @@ mod tests {
         assert_eq!(Fumen::decode("v115@vhAAgWQAIoMDEvoo2AXXaDEkoA6A"), Ok(fumen));
     }
 
+    #[test]
+    fn inherited_comments() {
+        let mut fumen = Fumen::default();
+        fumen.add_page().comment = Some("Hello World!".to_owned());
+        fumen.add_page();
+        fumen.add_page().comment = Some("Hello World!".to_owned());
+        fumen.add_page().comment = Some("".to_owned());
+        fumen.add_page();
+        assert_eq!(fumen.comments(), vec!["Hello World!", "Hello World!", "Hello World!", "", ""]);
+        assert_eq!(fumen.encode(), "v115@vhEAgWQAIoMDEvoo2AXXaDEkoA6AAAAAAAAAPAAAAA");
+
+        let decoded = Fumen::decode(&fumen.encode()).unwrap();
+        assert_eq!(decoded.pages[2].comment, None);
+        assert_eq!(decoded.comments(), fumen.comments());
+        let fumen = Fumen { pages: vec![Page::default()], ..Fumen::default() };
+        assert_eq!(fumen.comments(), vec![""]);
+    }
+
     #[test]
     fn comment_unicode() {
         let mut fumen = Fumen::default();
@@ -829,11 +1830,203 @@ mod tests {
         ), Ok(fumen));
     }
 
+    #[test]
+    fn try_encode() {
+        let mut fumen = Fumen::default();
+        fumen.add_page().comment = Some("Hello World!".to_owned());
+        assert_eq!(fumen.try_encode(), Ok(fumen.encode()));
+
+        fumen.add_page().comment = Some("a".repeat(4096));
+        assert_eq!(fumen.try_encode(), Err(EncodeFumenError::CommentTooLong { page: 1 }));
+
+        fumen.pages[1].comment = None;
+        fumen.pages[1].piece = Some(Piece {
+            kind: PieceType::I,
+            rotation: RotationState::South,
+            x: 0,
+            y: 0
+        });
+        assert_eq!(fumen.try_encode(), Err(EncodeFumenError::PieceOutOfBounds { page: 1 }));
+
+        fumen.pages[1].piece = Some(Piece {
+            kind: PieceType::I,
+            rotation: RotationState::South,
+            x: 2,
+            y: 0
+        });
+        fumen.pages[1].field[0][0] = CellColor::Grey;
+        assert_eq!(fumen.try_encode(), Err(EncodeFumenError::PieceOverlapsField { page: 1 }));
+    }
+
+    #[test]
+    fn validate() {
+        let t = |x, y| Some(Piece {
+            kind: PieceType::T,
+            rotation: RotationState::North,
+            x, y
+        });
+        let mut fumen = Fumen::default();
+        let page = fumen.add_page();
+        page.field[0][0] = CellColor::Grey;
+        page.piece = t(1, 1);
+        let mut page = Page { piece: t(5, 2), ..Page::default() };
+        fumen.pages.push(page.clone());
+        page.lock = false;
+        page.garbage_row[3] = CellColor::T;
+        page.garbage_row[4] = CellColor::Grey;
+        fumen.pages.push(page);
+        let mut page = Page { piece: t(0, 0), ..Page::default() };
+        fumen.pages.push(page.clone());
+        page.field[0][0] = CellColor::Grey;
+        page.piece = t(1, 0);
+        fumen.pages.push(page);
+        assert_eq!(fumen.validate(), vec![
+            Diagnostic { page: 1, problem: PageProblem::FloatingPiece },
+            Diagnostic { page: 2, problem: PageProblem::NonGreyGarbage { x: 3 } },
+            Diagnostic { page: 3, problem: PageProblem::PieceOutOfBounds },
+            Diagnostic { page: 4, problem: PageProblem::PieceOverlapsField },
+        ]);
+    }
+
+    #[test]
+    fn truncated_comment_escapes() {
+        let mut fumen = Fumen::default();
+        fumen.add_page().comment = Some(format!("{}あ", "a".repeat(4092)));
+        fumen.add_page().comment = Some(format!("{}🂡", "a".repeat(4089)));
+        fumen.add_page().comment = Some(format!("{}!", "a".repeat(4093)));
+        let decoded = Fumen::decode(&fumen.encode()).unwrap();
+        assert_eq!(decoded.pages[0].comment, Some("a".repeat(4092)));
+        assert_eq!(decoded.pages[1].comment, Some("a".repeat(4089)));
+        assert_eq!(decoded.pages[2].comment, Some("a".repeat(4093)));
+    }
+
+    #[test]
+    fn legacy_v110() {
+        let mut fumen = Fumen::default();
+        let page = fumen.add_page();
+        page.field[0][0] = CellColor::Grey;
+        page.field[20][9] = CellColor::I;
+        page.garbage_row[1] = CellColor::Grey;
+        page.piece = Some(Piece {
+            kind: PieceType::T,
+            rotation: RotationState::North,
+            x: 2,
+            y: 1
+        });
+        fumen.add_page().comment = Some("v110".to_owned());
+        let v110 = "v110@ob8edeA3pbA3nbVYI7eAAwNEA2qm2A";
+        assert_eq!(Fumen::decode(v110), Ok(fumen.clone()));
+        assert_eq!(Fumen::decode(&fumen.encode()), Ok(fumen));
+        assert_eq!(
+            Fumen::decode("v110@7eA/jI"),
+            Err(DecodeFumenError::InvalidPiecePosition { offset: 8, page: 0 })
+        );
+    }
+
+    #[test]
+    fn prefixes() {
+        let mut fumen = Fumen::default();
+        fumen.add_page().comment = Some("Hello World!".to_owned());
+        for &(mode, prefix) in &[
+            (ViewMode::View, "v115@"),
+            (ViewMode::Mobile, "m115@"),
+            (ViewMode::Data, "d115@"),
+            (ViewMode::Editor, "D115@")
+        ] {
+            let data = fumen.encode_with_prefix(mode);
+            assert_eq!(data, format!("{}vhAAgWQAIoMDEvoo2AXXaDEkoA6A", prefix));
+            let expected = Prefix { mode, version: Version::V115 };
+            assert_eq!(expected.to_string(), prefix);
+            assert_eq!(Fumen::decode_with_prefix(&data), Ok((fumen.clone(), expected)));
+        }
+        assert_eq!(
+            Fumen::decode_with_prefix("m110@7eAAAA").map(|(_, prefix)| prefix),
+            Ok(Prefix { mode: ViewMode::Mobile, version: Version::V110 })
+        );
+        assert_eq!(
+            Fumen::decode("x115@vhAAAA"),
+            Err(DecodeFumenError::UnsupportedVersion { offset: 0, page: 0 })
+        );
+    }
+
     #[test]
     fn not_a_fumen() {
-        assert_eq!(Fumen::decode(""), Err(DecodeFumenError));
-        assert_eq!(Fumen::decode("v115@hello world"), Err(DecodeFumenError));
-        assert_eq!(Fumen::decode("無効"), Err(DecodeFumenError));
+        assert_eq!(
+            Fumen::decode(""),
+            Err(DecodeFumenError::UnsupportedVersion { offset: 0, page: 0 })
+        );
+        assert_eq!(
+            Fumen::decode("v115@hello world"),
+            Err(DecodeFumenError::InvalidCharacter { offset: 10, page: 0, found: ' ' })
+        );
+        assert_eq!(
+            Fumen::decode("無効"),
+            Err(DecodeFumenError::UnsupportedVersion { offset: 0, page: 0 })
+        );
+        assert_eq!(
+            Fumen::decode("éabcd"),
+            Err(DecodeFumenError::UnsupportedVersion { offset: 0, page: 0 })
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(
+            Fumen::decode("v115@vhAAgH無"),
+            Err(DecodeFumenError::InvalidCharacter { offset: 11, page: 1, found: '無' })
+        );
+        assert_eq!(
+            Fumen::decode("v115@vhAAgHA"),
+            Err(DecodeFumenError::TruncatedField { offset: 12, page: 1 })
+        );
+        assert_eq!(
+            Fumen::decode("v115@bhJ8/8AgH"),
+            Err(DecodeFumenError::FieldOverflow { offset: 9, page: 0 })
+        );
+        assert_eq!(
+            Fumen::decode("v115@AAvhAgH"),
+            Err(DecodeFumenError::InvalidCellColor { offset: 5, page: 0 })
+        );
+        assert_eq!(
+            Fumen::decode("v115@vhAAg"),
+            Err(DecodeFumenError::TruncatedPage { offset: 10, page: 0 })
+        );
+        assert_eq!(
+            Fumen::decode("v115@vhA/VJ"),
+            Err(DecodeFumenError::InvalidPiecePosition { offset: 8, page: 0 })
+        );
+        assert_eq!(
+            Fumen::decode("v115@vhAAgWQAIoMD"),
+            Err(DecodeFumenError::TruncatedComment { offset: 17, page: 0 })
+        );
+        assert_eq!(
+            Fumen::decode("v115@vhAAgWBA///"),
+            Err(DecodeFumenError::TruncatedComment { offset: 16, page: 0 })
+        );
+        assert_eq!(
+            Fumen::decode("v115@vhAAgWBA/////"),
+            Err(DecodeFumenError::InvalidCommentCharacter { offset: 13, page: 0 })
+        );
+    }
+
+    #[test]
+    fn decode_every_page_number() {
+        for number in 0..64 * 64 * 64 {
+            let data: String = [number, number >> 6, number >> 12].iter()
+                .map(|&v| BASE64_CHARS[v & 0x3F] as char)
+                .collect();
+            match Fumen::decode(&format!("v115@vhA{}", data)) {
+                Ok(fumen) => for page in &fumen.pages {
+                    if let Some(piece) = page.piece {
+                        assert!(piece.in_field(), "{:?} from {}", piece, data);
+                    }
+                    page.next_page();
+                }
+                Err(DecodeFumenError::InvalidPiecePosition { offset: 8, page: 0 }) => {}
+                Err(DecodeFumenError::TruncatedComment { .. }) => {}
+                Err(e) => panic!("unexpected error {:?} from {}", e, data)
+            }
+        }
     }
 
     #[test]
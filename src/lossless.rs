@@ -0,0 +1,147 @@
+use crate::{decode_piece, DecodeFumenError, DecodeOptions, Fumen, Prefix};
+
+/// The details of how a fumen was encoded which don't affect what it decodes to.
+///
+/// Fumen data can be written in many ways which all decode to the same pages: separators can be
+/// inserted anywhere, runs of field cells can be split up, comments can be repeated or escaped
+/// differently, and so on. Since [`Fumen::encode`] always produces the same canonical data, this
+/// is kept around by [`Fumen::decode_lossless`] so that the original data can be reproduced.
+///
+/// With the `serde` feature this can be serialized but not deserialized, since the encoder relies
+/// on it matching data which was actually decoded.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Encoding {
+    pub(crate) prefix: Prefix,
+    /// Where `?` separators were, as offsets into the data with the prefix and separators removed.
+    pub(crate) separators: Vec<usize>,
+    pub(crate) pages: Vec<PageEncoding>
+}
+
+impl Encoding {
+    /// The prefix the data started with.
+    pub fn prefix(&self) -> Prefix {
+        self.prefix
+    }
+}
+
+/// How a single page was encoded.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub(crate) struct PageEncoding {
+    /// The raw field runs, or `None` if the field was reused by an empty field sequence.
+    pub(crate) field: Option<Vec<u16>>,
+    /// The raw length of the empty field sequence the field started, or 0 if it didn't start one.
+    /// Cut off data can have a sequence which runs past the last page.
+    pub(crate) empty_fields: usize,
+    /// The raw piece and flags number.
+    pub(crate) action: usize,
+    /// The comment, still escaped.
+    pub(crate) comment: Option<String>
+}
+
+impl Fumen {
+    /// Decodes a fumen data string, remembering how it was encoded.
+    ///
+    /// The pages are the same as those returned by [`Fumen::decode`]. As long as the fumen isn't
+    /// modified, [`Fumen::encode`] reproduces `data` exactly. Pages which are modified are encoded
+    /// normally, while the rest keep their original encoding. Only `v115` data is reproduced; the
+    /// encoding of `v110` data is not kept.
+    pub fn decode_lossless(data: &str) -> Result<Fumen, DecodeFumenError> {
+        Fumen::decode_lossless_with_options(data, DecodeOptions::default())
+    }
+
+    /// Decodes a fumen data string, remembering how it was encoded, with limits on the resources
+    /// used.
+    ///
+    /// See [`Fumen::decode_lossless`] and [`Fumen::decode_with_options`] for details.
+    pub fn decode_lossless_with_options(
+        data: &str, options: DecodeOptions
+    ) -> Result<Fumen, DecodeFumenError> {
+        Fumen::decode_inner(data, options, true).map(|(fumen, _)| fumen)
+    }
+}
+
+/// Whether the raw field runs decode to `deltas`.
+pub(crate) fn runs_match(runs: &[u16], deltas: &[[usize; 10]; 24]) -> bool {
+    let mut cells = deltas.iter().flat_map(|row| row.iter());
+    runs.iter().all(|&number| {
+        let value = number as usize / 240;
+        let repeats = number as usize % 240 + 1;
+        cells.by_ref().take(repeats).filter(|&&delta| delta == value).count() == repeats
+    }) && cells.next().is_none()
+}
+
+/// Whether the raw piece and flags number decodes to the same thing as `canonical`.
+pub(crate) fn action_matches(raw: usize, canonical: usize, first: bool) -> bool {
+    // the guideline flag is only read on the first page
+    let mask = if first { 0b11111 } else { 0b11011 };
+    (raw / 7680) & mask == (canonical / 7680) & mask &&
+        decode_piece(raw, 23) == decode_piece(canonical, 23)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    /// Data which decodes to the same pages as `canonical`, but is encoded differently.
+    const NON_CANONICAL: &[(&str, &str)] = &[
+        // separators
+        ("v115@vhAAgWQA?IoMDEvoo2AXXaDE?koA6A", "v115@vhAAgWQAIoMDEvoo2AXXaDEkoA6A"),
+        // empty field written out instead of continuing the empty field sequence
+        ("v115@vhAAgHvhAAgH", "v115@vhBAgHAAA"),
+        // empty field split into two runs
+        ("v115@3f3fAgH", "v115@vhAAgH"),
+        // field runs split up
+        ("v115@A8jfKgAgH", "v115@A8uhAgH"),
+        // rotation and position bits with no piece
+        ("v115@vhAoiH", "v115@vhAAgH"),
+        // repeated comment
+        (
+            "v115@vhBAgWQAIoMDEvoo2AXXaDEkoA6AAgWQAIoMDEvoo2AXXaDEkoA6A",
+            "v115@vhBAgWQAIoMDEvoo2AXXaDEkoA6AAAA"
+        ),
+        // empty field sequence running past the last page
+        ("v115@vhEAgHAAA", "v115@vhBAgHAAA"),
+        ("v115@vhBAgH", "v115@vhAAgH"),
+        // guideline flag on later pages
+        ("v115@vhBAgHAgH", "v115@vhBAgHAAA"),
+        // lowercase escape
+        ("v115@vhAAgWDAl35AA", "v115@vhAAgWDAlH5AA"),
+    ];
+
+    #[test]
+    fn lossless_round_trip() {
+        for &(data, canonical) in NON_CANONICAL {
+            let fumen = Fumen::decode_lossless(data).unwrap();
+            let decoded = Fumen::decode(data).unwrap();
+            assert_eq!(fumen, decoded, "{}", data);
+            let hash = |f: &Fumen| {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                std::hash::Hash::hash(f, &mut hasher);
+                std::hash::Hasher::finish(&hasher)
+            };
+            assert_eq!(hash(&fumen), hash(&decoded), "{}", data);
+            assert_eq!(fumen.encode(), data);
+            assert_eq!(decoded.encode(), canonical);
+            assert_eq!(fumen.encoding.unwrap().prefix().mode, ViewMode::View);
+        }
+    }
+
+    #[test]
+    fn lossless_edits() {
+        let mut fumen = Fumen::decode_lossless("v115@vhAAgWQA?IoMDEvoo2AXXaDE?koA6A").unwrap();
+        fumen.pages[0].comment = Some("Hello!".to_owned());
+        fumen.add_page();
+        let encoded = fumen.encode();
+        assert!(encoded.contains('?'));
+        assert_eq!(Fumen::decode(&encoded), Ok(fumen));
+
+        let mut fumen = Fumen::decode_lossless("v115@3f3fAgH").unwrap();
+        fumen.pages[0].field[22][0] = CellColor::Grey;
+        assert_eq!(fumen.encode(), "v115@A8uhAgH");
+
+        let fumen = Fumen::decode_lossless("m110@7eAA4G").unwrap();
+        assert_eq!(fumen.encoding, None);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{BitField, Page, Piece, RotationDirection, RotationSystem, Srs};
+
+impl Page {
+    /// Whether the piece overlaps filled cells of the field or lies partly outside of it.
+    pub fn collides(&self, piece: &Piece) -> bool {
+        BitField::from(&self.field).collides(piece)
+    }
+
+    /// Moves the piece one column left, or returns `None` if it is blocked.
+    pub fn shift_left(&self, piece: &Piece) -> Option<Piece> {
+        BitField::from(&self.field).shift_left(piece)
+    }
+
+    /// Moves the piece one column right, or returns `None` if it is blocked.
+    pub fn shift_right(&self, piece: &Piece) -> Option<Piece> {
+        BitField::from(&self.field).shift_right(piece)
+    }
+
+    /// Moves the piece one row down, or returns `None` if it is blocked.
+    pub fn soft_drop(&self, piece: &Piece) -> Option<Piece> {
+        BitField::from(&self.field).soft_drop(piece)
+    }
+
+    /// Moves the piece down as far as it goes.
+    pub fn hard_drop(&self, piece: &Piece) -> Piece {
+        BitField::from(&self.field).hard_drop(piece)
+    }
+
+    /// Rotates the piece using the rotation system, or returns `None` if every kick is blocked.
+    pub fn rotate<S: RotationSystem + ?Sized>(
+        &self, piece: &Piece, direction: RotationDirection, system: &S
+    ) -> Option<Piece> {
+        system.rotate(self, piece, direction)
+    }
+
+    /// Rotates the piece clockwise using SRS kicks, or returns `None` if every kick is blocked.
+    pub fn rotate_cw(&self, piece: &Piece) -> Option<Piece> {
+        self.rotate(piece, RotationDirection::Cw, &Srs)
+    }
+
+    /// Rotates the piece counter-clockwise using SRS kicks, or returns `None` if every kick is
+    /// blocked.
+    pub fn rotate_ccw(&self, piece: &Piece) -> Option<Piece> {
+        self.rotate(piece, RotationDirection::Ccw, &Srs)
+    }
+
+    /// Rotates the piece 180 degrees in place, or returns `None` if it is blocked.
+    ///
+    /// SRS has no kicks for 180 degree rotations.
+    pub fn rotate_180(&self, piece: &Piece) -> Option<Piece> {
+        self.rotate(piece, RotationDirection::Flip, &Srs)
+    }
+}
+
+/// The same movements as on [`Page`], without converting the field for every one.
+impl BitField {
+    /// Moves the piece one column left, or returns `None` if it is blocked.
+    pub fn shift_left(&self, piece: &Piece) -> Option<Piece> {
+        self.try_move(piece, (-1, 0))
+    }
+
+    /// Moves the piece one column right, or returns `None` if it is blocked.
+    pub fn shift_right(&self, piece: &Piece) -> Option<Piece> {
+        self.try_move(piece, (1, 0))
+    }
+
+    /// Moves the piece one row down, or returns `None` if it is blocked.
+    pub fn soft_drop(&self, piece: &Piece) -> Option<Piece> {
+        self.try_move(piece, (0, -1))
+    }
+
+    /// Moves the piece down as far as it goes.
+    pub fn hard_drop(&self, piece: &Piece) -> Piece {
+        let mut piece = *piece;
+        while let Some(dropped) = self.soft_drop(&piece) {
+            piece = dropped;
+        }
+        piece
+    }
+
+    fn try_move(&self, piece: &Piece, offset: (i32, i32)) -> Option<Piece> {
+        piece.moved(piece.rotation, offset).filter(|p| !self.collides(p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn piece(kind: PieceType, rotation: RotationState, x: i32, y: i32) -> Piece {
+        Piece { kind, rotation, x, y }
+    }
+
+    #[test]
+    fn shifts_and_drops() {
+        let mut page = Page::default();
+        page.field[5][4] = CellColor::Grey;
+        let t = piece(PieceType::T, RotationState::North, 2, 20);
+        assert_eq!(page.shift_left(&t), Some(piece(PieceType::T, RotationState::North, 1, 20)));
+        assert_eq!(page.shift_left(&page.shift_left(&t).unwrap()), None);
+        assert_eq!(page.shift_right(&t), Some(piece(PieceType::T, RotationState::North, 3, 20)));
+        assert_eq!(page.soft_drop(&t), Some(piece(PieceType::T, RotationState::North, 2, 19)));
+        assert_eq!(page.hard_drop(&t), piece(PieceType::T, RotationState::North, 2, 0));
+        let t = piece(PieceType::T, RotationState::North, 4, 20);
+        assert_eq!(page.hard_drop(&t), piece(PieceType::T, RotationState::North, 4, 6));
+        assert!(page.collides(&piece(PieceType::T, RotationState::North, 4, 5)));
+        assert!(page.collides(&piece(PieceType::T, RotationState::North, 4, 22)));
+        assert_eq!(page.soft_drop(&page.hard_drop(&t)), None);
+
+        let field = BitField::from(&page);
+        assert_eq!(field.hard_drop(&t), page.hard_drop(&t));
+        assert_eq!(field.shift_left(&t), page.shift_left(&t));
+        assert_eq!(field.soft_drop(&field.hard_drop(&t)), None);
+    }
+
+    #[test]
+    fn srs_kicks() {
+        let page = Page::default();
+        // kicked off of the wall
+        assert_eq!(
+            page.rotate_cw(&piece(PieceType::T, RotationState::East, 0, 1)),
+            Some(piece(PieceType::T, RotationState::South, 1, 1))
+        );
+        // the last I kick, up out of the floor
+        assert_eq!(
+            page.rotate_cw(&piece(PieceType::I, RotationState::North, 1, 0)),
+            Some(piece(PieceType::I, RotationState::East, 3, 2))
+        );
+        assert_eq!(
+            page.rotate_ccw(&piece(PieceType::L, RotationState::North, 4, 10)),
+            Some(piece(PieceType::L, RotationState::West, 4, 10))
+        );
+
+        // O pieces don't move
+        let mut o = piece(PieceType::O, RotationState::North, 0, 0);
+        for _ in 0..4 {
+            let rotated = page.rotate_cw(&o).unwrap();
+            assert_eq!(rotated.canonical(), o.canonical());
+            assert_eq!(page.rotate_ccw(&rotated), Some(o));
+            assert_eq!(page.rotate_180(&rotated).unwrap().canonical(), o.canonical());
+            o = rotated;
+        }
+        assert_eq!(
+            page.rotate_180(&piece(PieceType::I, RotationState::East, 4, 10)),
+            Some(piece(PieceType::I, RotationState::West, 3, 9))
+        );
+
+        assert_eq!(page.rotate_180(&piece(PieceType::T, RotationState::North, 4, 0)), None);
+    }
+
+    #[test]
+    fn rotations_undo() {
+        let page = Page::default();
+        for &kind in &[
+            PieceType::I, PieceType::L, PieceType::O, PieceType::Z,
+            PieceType::T, PieceType::J, PieceType::S
+        ] {
+            for &rotation in &[
+                RotationState::North, RotationState::East,
+                RotationState::South, RotationState::West
+            ] {
+                let piece = piece(kind, rotation, 4, 10);
+                assert_eq!(page.rotate_cw(&piece).and_then(|p| page.rotate_ccw(&p)), Some(piece));
+                assert_eq!(page.rotate_180(&piece).and_then(|p| page.rotate_180(&p)), Some(piece));
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::borrow::Cow;
+
+use crate::{Fumen, Page, PieceType};
+
+/// The piece queue of a quiz, written at the start of a comment as `#Q=[hold](current)next`.
+///
+/// For example, `#Q=[S](T)ZIOJL` has S in hold, T as the current piece, and ZIOJL as the next
+/// pieces. Any text after the queue is shown as a normal comment by fumen.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quiz {
+    pub hold: Option<PieceType>,
+    pub current: Option<PieceType>,
+    pub next: Vec<PieceType>
+}
+
+impl Quiz {
+    /// Parses the quiz at the start of a comment, also returning the text after it.
+    pub fn from_comment(comment: &str) -> Option<(Quiz, &str)> {
+        let rest = comment.strip_prefix("#Q=[")?;
+        let (hold, rest) = parse_slot(rest, ']')?;
+        let rest = rest.strip_prefix('(')?;
+        let (current, rest) = parse_slot(rest, ')')?;
+        let length = rest.find(|c| piece_from_letter(c).is_none()).unwrap_or(rest.len());
+        let next = rest[..length].chars().filter_map(piece_from_letter).collect();
+        Some((Quiz { hold, current, next }, &rest[length..]))
+    }
+
+    /// The quiz after `used` is placed, or `None` if the queue doesn't allow placing it.
+    ///
+    /// The current piece can always be used. The hold piece can be used by swapping it with the
+    /// current piece, and if the hold slot is empty, the first next piece can be used by moving
+    /// the current piece into hold.
+    pub fn advance(&self, used: PieceType) -> Option<Quiz> {
+        let mut next = self.next.iter().copied();
+        let mut hold = self.hold;
+        let current = self.current.or_else(|| next.next())?;
+        if used != current {
+            if hold == Some(used) || hold.is_none() && next.next() == Some(used) {
+                hold = Some(current);
+            } else {
+                return None;
+            }
+        }
+        let current = next.next();
+        Some(Quiz { hold, current, next: next.collect() })
+    }
+}
+
+impl Fumen {
+    /// Resolves the quiz each page shows, from the comments returned by [`Fumen::comments`].
+    pub fn quizzes(&self) -> Vec<Option<Quiz>> {
+        self.comments().iter()
+            .map(|comment| Quiz::from_comment(comment).map(|(quiz, _)| quiz))
+            .collect()
+    }
+
+    /// The comment each page shows if it doesn't have a comment of its own.
+    pub(crate) fn inherited_comments(&self) -> impl Iterator<Item=Cow<'_, str>> {
+        self.pages.iter().scan(Cow::Borrowed(""), |inherited, page| {
+            let shown = match page.comment {
+                Some(ref comment) => Cow::Borrowed(&comment[..]),
+                None => inherited.clone()
+            };
+            let next = advance_comment(&shown, page).map_or(shown, Cow::Owned);
+            Some(std::mem::replace(inherited, next))
+        })
+    }
+}
+
+/// The comment the page after `page` inherits if `page` shows a quiz and locks a piece.
+pub(crate) fn advance_comment(shown: &str, page: &Page) -> Option<String> {
+    let piece = page.piece.filter(|_| page.lock)?;
+    let (quiz, rest) = Quiz::from_comment(shown)?;
+    Some(format!("{}{}", quiz.advance(piece.kind)?, rest))
+}
+
+impl std::fmt::Display for Quiz {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let letter = |p: Option<PieceType>| p.map(piece_letter).into_iter().collect::<String>();
+        write!(fmt, "#Q=[{}]({})", letter(self.hold), letter(self.current))?;
+        for &p in &self.next {
+            write!(fmt, "{}", piece_letter(p))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Quiz {
+    type Err = ParseQuizError;
+
+    fn from_str(s: &str) -> Result<Quiz, ParseQuizError> {
+        match Quiz::from_comment(s) {
+            Some((quiz, "")) => Ok(quiz),
+            _ => Err(ParseQuizError)
+        }
+    }
+}
+
+/// Parses an optional piece followed by `close`.
+fn parse_slot(s: &str, close: char) -> Option<(Option<PieceType>, &str)> {
+    if let Some(rest) = s.strip_prefix(close) {
+        return Some((None, rest));
+    }
+    let mut chars = s.chars();
+    let piece = piece_from_letter(chars.next()?)?;
+    let rest = chars.as_str().strip_prefix(close)?;
+    Some((Some(piece), rest))
+}
+
+fn piece_letter(piece: PieceType) -> char {
+    match piece {
+        PieceType::I => 'I',
+        PieceType::L => 'L',
+        PieceType::O => 'O',
+        PieceType::Z => 'Z',
+        PieceType::T => 'T',
+        PieceType::J => 'J',
+        PieceType::S => 'S'
+    }
+}
+
+fn piece_from_letter(c: char) -> Option<PieceType> {
+    Some(match c.to_ascii_uppercase() {
+        'I' => PieceType::I,
+        'L' => PieceType::L,
+        'O' => PieceType::O,
+        'Z' => PieceType::Z,
+        'T' => PieceType::T,
+        'J' => PieceType::J,
+        'S' => PieceType::S,
+        _ => return None
+    })
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseQuizError;
+
+impl std::fmt::Display for ParseQuizError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "the string is not a quiz queue")
+    }
+}
+
+impl std::error::Error for ParseQuizError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn parse_and_print() {
+        let quiz = Quiz {
+            hold: Some(PieceType::S),
+            current: Some(PieceType::T),
+            next: vec![PieceType::Z, PieceType::I, PieceType::O, PieceType::J, PieceType::L]
+        };
+        assert_eq!("#Q=[S](T)ZIOJL".parse(), Ok(quiz.clone()));
+        assert_eq!(quiz.to_string(), "#Q=[S](T)ZIOJL");
+        assert_eq!(Quiz::from_comment("#Q=[s](t)zioJL;PCO"), Some((quiz, ";PCO")));
+        assert_eq!(
+            Quiz::from_comment("#Q=[]()"),
+            Some((Quiz { hold: None, current: None, next: vec![] }, ""))
+        );
+        assert_eq!("#Q=[S](T)ZIOJL;".parse::<Quiz>(), Err(ParseQuizError));
+        assert_eq!("#Q=[SZ](T)".parse::<Quiz>(), Err(ParseQuizError));
+        assert_eq!(Quiz::from_comment("Hello World!"), None);
+    }
+
+    #[test]
+    fn advance() {
+        let quiz: Quiz = "#Q=[](T)ZIOJL".parse().unwrap();
+        let quiz = quiz.advance(PieceType::T).unwrap();
+        assert_eq!(quiz.to_string(), "#Q=[](Z)IOJL");
+        let quiz = quiz.advance(PieceType::I).unwrap();
+        assert_eq!(quiz.to_string(), "#Q=[Z](O)JL");
+        assert_eq!(quiz.advance(PieceType::J), None);
+        let quiz = quiz.advance(PieceType::Z).unwrap();
+        assert_eq!(quiz.to_string(), "#Q=[O](J)L");
+        let quiz = quiz.advance(PieceType::J).unwrap();
+        assert_eq!(quiz.to_string(), "#Q=[O](L)");
+        let quiz = quiz.advance(PieceType::L).unwrap();
+        assert_eq!(quiz.to_string(), "#Q=[O]()");
+        assert_eq!(quiz.advance(PieceType::O), None);
+    }
+
+    #[test]
+    fn fumen_quizzes() {
+        let piece = |kind, x| Some(Piece { kind, rotation: RotationState::North, x, y: 0 });
+        let mut fumen = Fumen::default();
+        let page = fumen.add_page();
+        page.comment = Some("#Q=[](T)IO;build a PCO".to_owned());
+        page.piece = piece(PieceType::I, 1);
+        let page = fumen.add_page();
+        assert_eq!(page.comment, None);
+        page.piece = piece(PieceType::O, 5);
+        fumen.add_page();
+        assert_eq!(fumen.comments(), vec![
+            "#Q=[](T)IO;build a PCO", "#Q=[T](O);build a PCO", "#Q=[T]();build a PCO"
+        ]);
+
+        let decoded = Fumen::decode(&fumen.encode()).unwrap();
+        assert_eq!(decoded, fumen);
+        assert_eq!(decoded.quizzes(), vec![
+            Some(Quiz {
+                hold: None,
+                current: Some(PieceType::T),
+                next: vec![PieceType::I, PieceType::O]
+            }),
+            Some(Quiz { hold: Some(PieceType::T), current: Some(PieceType::O), next: vec![] }),
+            Some(Quiz { hold: Some(PieceType::T), current: None, next: vec![] }),
+        ]);
+    }
+}
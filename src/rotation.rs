@@ -0,0 +1,633 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::fmt;
+
+use crate::{BitField, Page, Piece, PieceType, RotationState};
+
+/// A direction to rotate a piece in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RotationDirection {
+    Cw,
+    Ccw,
+    Flip
+}
+
+impl RotationDirection {
+    /// The state after rotating a piece in `rotation` in this direction.
+    pub fn apply(self, rotation: RotationState) -> RotationState {
+        match self {
+            RotationDirection::Cw => rotation.cw(),
+            RotationDirection::Ccw => rotation.ccw(),
+            RotationDirection::Flip => rotation.flip()
+        }
+    }
+}
+
+/// Decides where pieces end up when they rotate.
+///
+/// Positions and kicks are always in terms of [`Piece`]'s SRS rotation centers, so systems that
+/// rotate about other points express that as part of their kicks. Custom systems implement this
+/// trait and can be used anywhere the built-in ones can, or added to a [`RotationSystems`].
+pub trait RotationSystem {
+    /// The offsets to try, in order, when rotating a piece of `kind` from `from` in `direction`.
+    /// An empty list means the rotation is not possible.
+    fn kicks(
+        &self, kind: PieceType, from: RotationState, direction: RotationDirection
+    ) -> &[(i32, i32)];
+
+    /// The rotation state pieces of `kind` spawn in.
+    fn spawn_rotation(&self, _kind: PieceType) -> RotationState {
+        RotationState::North
+    }
+
+    /// Rotates the piece on the page, or returns `None` if every kick is blocked.
+    ///
+    /// By default this uses the first of [`RotationSystem::kicks`] that doesn't collide.
+    fn rotate(&self, page: &Page, piece: &Piece, direction: RotationDirection) -> Option<Piece> {
+        let field = BitField::from(page);
+        let rotation = direction.apply(piece.rotation);
+        self.kicks(piece.kind, piece.rotation, direction).iter()
+            .filter_map(|&kick| piece.moved(rotation, kick))
+            .find(|p| !field.collides(p))
+    }
+}
+
+/// Kicks to try for each starting rotation state, in the order of [`RotationState`]'s values.
+///
+/// The built-in tables borrow static data, while tables built at runtime or deserialized own
+/// their kicks.
+pub type Kicks = [Cow<'static, [(i32, i32)]>; 4];
+
+/// The kicks a rotation system uses for a group of pieces.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KickTable {
+    /// Kicks for clockwise rotations.
+    pub cw: Kicks,
+    /// Kicks for counterclockwise rotations.
+    pub ccw: Kicks,
+    /// Kicks for 180 degree rotations.
+    pub flip: Kicks
+}
+
+impl KickTable {
+    /// The kicks for rotating from `from` in `direction`.
+    pub fn kicks(&self, from: RotationState, direction: RotationDirection) -> &[(i32, i32)] {
+        let kicks = match direction {
+            RotationDirection::Cw => &self.cw,
+            RotationDirection::Ccw => &self.ccw,
+            RotationDirection::Flip => &self.flip
+        };
+        &kicks[from as usize]
+    }
+}
+
+type StaticKicks = [&'static [(i32, i32)]; 4];
+
+/// The data of a built-in [`KickTable`], which can be copied and borrowed for `'static`.
+#[derive(Copy, Clone)]
+struct StaticTable {
+    cw: StaticKicks,
+    ccw: StaticKicks,
+    flip: StaticKicks
+}
+
+impl StaticTable {
+    const fn table(&self) -> KickTable {
+        const fn kicks(kicks: &StaticKicks) -> Kicks {
+            [
+                Cow::Borrowed(kicks[0]),
+                Cow::Borrowed(kicks[1]),
+                Cow::Borrowed(kicks[2]),
+                Cow::Borrowed(kicks[3])
+            ]
+        }
+        KickTable { cw: kicks(&self.cw), ccw: kicks(&self.ccw), flip: kicks(&self.flip) }
+    }
+
+    fn kicks(&self, from: RotationState, direction: RotationDirection) -> &'static [(i32, i32)] {
+        let kicks = match direction {
+            RotationDirection::Cw => &self.cw,
+            RotationDirection::Ccw => &self.ccw,
+            RotationDirection::Flip => &self.flip
+        };
+        kicks[from as usize]
+    }
+}
+
+const NO_KICKS: StaticKicks = [&[], &[], &[], &[]];
+
+const SRS_JLSTZ: StaticTable = StaticTable {
+    cw: [
+        /* South */ &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+        /* East */ &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+        /* North */ &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+        /* West */ &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]
+    ],
+    ccw: [
+        /* South */ &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+        /* East */ &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+        /* North */ &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+        /* West */ &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]
+    ],
+    flip: [&[(0, 0)], &[(0, 0)], &[(0, 0)], &[(0, 0)]]
+};
+const SRS_I: StaticTable = StaticTable {
+    cw: [
+        /* South */ &[(-1, 0), (1, 0), (-2, 0), (1, 1), (-2, -2)],
+        /* East */ &[(0, -1), (-1, -1), (2, -1), (-1, 1), (2, -2)],
+        /* North */ &[(1, 0), (-1, 0), (2, 0), (-1, -1), (2, 2)],
+        /* West */ &[(0, 1), (1, 1), (-2, 1), (1, -1), (-2, 2)]
+    ],
+    ccw: [
+        /* South */ &[(0, 1), (1, 1), (-2, 1), (1, -1), (-2, 2)],
+        /* East */ &[(-1, 0), (1, 0), (-2, 0), (1, 1), (-2, -2)],
+        /* North */ &[(0, -1), (-1, -1), (2, -1), (-1, 1), (2, -2)],
+        /* West */ &[(1, 0), (-1, 0), (2, 0), (-1, -1), (2, 2)]
+    ],
+    flip: [&[(-1, 1)], &[(-1, -1)], &[(1, -1)], &[(1, 1)]]
+};
+const SRS_O: StaticTable = StaticTable {
+    cw: [&[(0, -1)], &[(1, 0)], &[(0, 1)], &[(-1, 0)]],
+    ccw: [&[(-1, 0)], &[(0, -1)], &[(1, 0)], &[(0, 1)]],
+    flip: [&[(-1, -1)], &[(1, -1)], &[(1, 1)], &[(-1, 1)]]
+};
+
+const SRS_PLUS_JLSTZ: StaticTable = StaticTable {
+    flip: [
+        /* South */ &[(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)],
+        /* East */ &[(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],
+        /* North */ &[(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],
+        /* West */ &[(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)]
+    ],
+    ..SRS_JLSTZ
+};
+const SRS_PLUS_I: StaticTable = StaticTable {
+    cw: [
+        /* South */ &[(-1, 0), (1, 0), (-2, 0), (1, 1), (-2, -2)],
+        /* East */ &[(0, -1), (-1, -1), (2, -1), (-1, 1), (2, -2)],
+        /* North */ &[(1, 0), (2, 0), (-1, 0), (-1, -1), (2, 2)],
+        /* West */ &[(0, 1), (1, 1), (-2, 1), (1, 3), (-2, 0)]
+    ],
+    ccw: [
+        /* South */ &[(0, 1), (-2, 1), (1, 1), (-2, 2), (1, -1)],
+        /* East */ &[(-1, 0), (-2, 0), (1, 0), (-2, -2), (1, 1)],
+        /* North */ &[(0, -1), (-1, -1), (2, -1), (2, -2), (-1, 1)],
+        /* West */ &[(1, 0), (2, 0), (-1, 0), (2, -2), (-1, 1)]
+    ],
+    ..SRS_I
+};
+
+const ARS_JLT: StaticTable = StaticTable {
+    cw: [
+        /* South */ &[(0, 0), (1, 0), (-1, 0)],
+        /* East */ &[(0, 0), (1, 0), (-1, 0)],
+        /* North */ &[(0, 1), (1, 1), (-1, 1)],
+        /* West */ &[(0, -1), (1, -1), (-1, -1)]
+    ],
+    ccw: [
+        /* South */ &[(0, 0), (1, 0), (-1, 0)],
+        /* East */ &[(0, -1), (1, -1), (-1, -1)],
+        /* North */ &[(0, 1), (1, 1), (-1, 1)],
+        /* West */ &[(0, 0), (1, 0), (-1, 0)]
+    ],
+    flip: NO_KICKS
+};
+const ARS_S: StaticTable = StaticTable {
+    cw: [
+        /* South */ &[(0, 0), (1, 0), (-1, 0)],
+        /* East */ &[(1, 0), (2, 0), (0, 0)],
+        /* North */ &[(-1, 1), (0, 1), (-2, 1)],
+        /* West */ &[(0, -1), (1, -1), (-1, -1)]
+    ],
+    ccw: [
+        /* South */ &[(-1, 0), (0, 0), (-2, 0)],
+        /* East */ &[(1, -1), (2, -1), (0, -1)],
+        /* North */ &[(0, 1), (1, 1), (-1, 1)],
+        /* West */ &[(0, 0), (1, 0), (-1, 0)]
+    ],
+    flip: NO_KICKS
+};
+const ARS_Z: StaticTable = StaticTable {
+    cw: [
+        /* South */ &[(1, 0), (2, 0), (0, 0)],
+        /* East */ &[(0, 0), (1, 0), (-1, 0)],
+        /* North */ &[(0, 1), (1, 1), (-1, 1)],
+        /* West */ &[(-1, -1), (0, -1), (-2, -1)]
+    ],
+    ccw: [
+        /* South */ &[(0, 0), (1, 0), (-1, 0)],
+        /* East */ &[(0, -1), (1, -1), (-1, -1)],
+        /* North */ &[(1, 1), (2, 1), (0, 1)],
+        /* West */ &[(-1, 0), (0, 0), (-2, 0)]
+    ],
+    flip: NO_KICKS
+};
+const ARS_I: StaticTable = StaticTable {
+    cw: [&[(0, -1)], &[(0, 0)], &[(1, 0)], &[(-1, 1)]],
+    ccw: [&[(0, 0)], &[(-1, 0)], &[(1, -1)], &[(0, 1)]],
+    flip: NO_KICKS
+};
+const ARS_O: StaticTable = StaticTable { flip: NO_KICKS, ..SRS_O };
+
+const CLASSIC_JLT: StaticTable = StaticTable {
+    cw: [&[(0, 0)], &[(0, 0)], &[(0, 0)], &[(0, 0)]],
+    ccw: [&[(0, 0)], &[(0, 0)], &[(0, 0)], &[(0, 0)]],
+    flip: NO_KICKS
+};
+const CLASSIC_SZ: StaticTable = StaticTable {
+    cw: [&[(1, 0)], &[(0, 0)], &[(0, 1)], &[(-1, -1)]],
+    ccw: [&[(0, 0)], &[(0, -1)], &[(1, 1)], &[(-1, 0)]],
+    flip: NO_KICKS
+};
+const CLASSIC_I: StaticTable = StaticTable {
+    cw: [&[(0, 0)], &[(0, -1)], &[(1, 1)], &[(-1, 0)]],
+    ccw: [&[(0, 1)], &[(-1, -1)], &[(1, 0)], &[(0, 0)]],
+    flip: NO_KICKS
+};
+const CLASSIC_O: StaticTable = ARS_O;
+
+/// Guideline SRS.
+///
+/// SRS has no 180 degree rotations, so those only rotate about the true rotation center.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Srs;
+
+impl Srs {
+    /// Kicks for J, L, S, T and Z pieces.
+    pub const JLSTZ: KickTable = SRS_JLSTZ.table();
+    /// Kicks for I pieces.
+    pub const I: KickTable = SRS_I.table();
+    /// Kicks for O pieces, which keep the piece in place as its rotation center moves.
+    pub const O: KickTable = SRS_O.table();
+}
+
+impl RotationSystem for Srs {
+    fn kicks(
+        &self, kind: PieceType, from: RotationState, direction: RotationDirection
+    ) -> &[(i32, i32)] {
+        match kind {
+            PieceType::I => SRS_I.kicks(from, direction),
+            PieceType::O => SRS_O.kicks(from, direction),
+            _ => SRS_JLSTZ.kicks(from, direction)
+        }
+    }
+}
+
+/// TETR.IO's SRS+, which has symmetric I kicks and kicks for 180 degree rotations.
+///
+/// Only J, L, S, T and Z pieces kick when rotating 180 degrees.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SrsPlus;
+
+impl SrsPlus {
+    /// Kicks for J, L, S, T and Z pieces, which are [`Srs::JLSTZ`] plus 180 degree kicks.
+    pub const JLSTZ: KickTable = SRS_PLUS_JLSTZ.table();
+    /// Kicks for I pieces, mirrored between clockwise and counterclockwise rotations.
+    pub const I: KickTable = SRS_PLUS_I.table();
+    /// Kicks for O pieces, the same as [`Srs::O`].
+    pub const O: KickTable = SRS_O.table();
+}
+
+impl RotationSystem for SrsPlus {
+    fn kicks(
+        &self, kind: PieceType, from: RotationState, direction: RotationDirection
+    ) -> &[(i32, i32)] {
+        match kind {
+            PieceType::I => SRS_PLUS_I.kicks(from, direction),
+            PieceType::O => SRS_O.kicks(from, direction),
+            _ => SRS_PLUS_JLSTZ.kicks(from, direction)
+        }
+    }
+}
+
+/// Arika's rotation system from TGM.
+///
+/// Pieces sit at the bottom of their bounding box and J, L and T pieces spawn flat side up. J, L,
+/// S, T and Z pieces try one column right and then one column left if their rotation is blocked,
+/// except that J, L and T pieces don't kick when the first blocked cell in reading order is in
+/// the middle column. There are no 180 degree rotations.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ars;
+
+impl Ars {
+    /// Kicks for J, L and T pieces. The first kick is the basic rotation.
+    pub const JLT: KickTable = ARS_JLT.table();
+    /// Kicks for S pieces. The first kick is the basic rotation.
+    pub const S: KickTable = ARS_S.table();
+    /// Kicks for Z pieces. The first kick is the basic rotation.
+    pub const Z: KickTable = ARS_Z.table();
+    /// Kicks for I pieces, which don't kick.
+    pub const I: KickTable = ARS_I.table();
+    /// Kicks for O pieces, the same as [`Srs::O`] without 180 degree rotations.
+    pub const O: KickTable = ARS_O.table();
+}
+
+impl RotationSystem for Ars {
+    fn kicks(
+        &self, kind: PieceType, from: RotationState, direction: RotationDirection
+    ) -> &[(i32, i32)] {
+        let table = match kind {
+            PieceType::I => &ARS_I,
+            PieceType::O => &ARS_O,
+            PieceType::S => &ARS_S,
+            PieceType::Z => &ARS_Z,
+            _ => &ARS_JLT
+        };
+        table.kicks(from, direction)
+    }
+
+    fn spawn_rotation(&self, kind: PieceType) -> RotationState {
+        match kind {
+            PieceType::J | PieceType::L | PieceType::T => RotationState::South,
+            _ => RotationState::North
+        }
+    }
+
+    fn rotate(&self, page: &Page, piece: &Piece, direction: RotationDirection) -> Option<Piece> {
+        let field = BitField::from(page);
+        let rotation = direction.apply(piece.rotation);
+        let (&basic, kicks) = self.kicks(piece.kind, piece.rotation, direction).split_first()?;
+        let rotated = piece.moved(rotation, basic)?;
+        if !field.collides(&rotated) {
+            return Some(rotated);
+        }
+        let mut cells = rotated.checked_cells()?;
+        cells.sort_by_key(|&(x, y)| (Reverse(y), x));
+        let blocked = cells.iter().find(|&&(x, y)| field.blocked(x, y));
+        let center_blocked = blocked.map(|&(x, _)| x) == Some(rotated.x);
+        match piece.kind {
+            PieceType::J | PieceType::L | PieceType::T if center_blocked => None,
+            _ => kicks.iter()
+                .filter_map(|&kick| piece.moved(rotation, kick))
+                .find(|p| !field.collides(p))
+        }
+    }
+}
+
+/// NES-style rotation, without kicks or 180 degree rotations.
+///
+/// J, L and T pieces spawn flat side up, and I, S and Z pieces only have two orientations.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Classic;
+
+impl Classic {
+    /// Kicks for J, L and T pieces, which rotate in place.
+    pub const JLT: KickTable = CLASSIC_JLT.table();
+    /// Kicks for S and Z pieces, which alternate between two orientations.
+    pub const SZ: KickTable = CLASSIC_SZ.table();
+    /// Kicks for I pieces, which alternate between two orientations.
+    pub const I: KickTable = CLASSIC_I.table();
+    /// Kicks for O pieces, the same as [`Srs::O`] without 180 degree rotations.
+    pub const O: KickTable = CLASSIC_O.table();
+}
+
+impl RotationSystem for Classic {
+    fn kicks(
+        &self, kind: PieceType, from: RotationState, direction: RotationDirection
+    ) -> &[(i32, i32)] {
+        let table = match kind {
+            PieceType::I => &CLASSIC_I,
+            PieceType::O => &CLASSIC_O,
+            PieceType::S | PieceType::Z => &CLASSIC_SZ,
+            _ => &CLASSIC_JLT
+        };
+        table.kicks(from, direction)
+    }
+
+    fn spawn_rotation(&self, kind: PieceType) -> RotationState {
+        match kind {
+            PieceType::O => RotationState::North,
+            _ => RotationState::South
+        }
+    }
+}
+
+/// Rotation systems by name, for picking one at runtime.
+///
+/// [`RotationSystems::default`] contains the built-in systems as `srs`, `srs+`, `ars` and
+/// `classic`.
+pub struct RotationSystems {
+    systems: Vec<(String, Box<dyn RotationSystem + Send + Sync>)>
+}
+
+impl RotationSystems {
+    /// Creates a registry with no systems in it.
+    pub fn empty() -> Self {
+        RotationSystems { systems: vec![] }
+    }
+
+    /// Adds a system, replacing any system already registered with the same name.
+    pub fn register(
+        &mut self, name: impl Into<String>, system: impl RotationSystem + Send + Sync + 'static
+    ) {
+        let name = name.into();
+        let system = Box::new(system);
+        match self.systems.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = system,
+            None => self.systems.push((name, system))
+        }
+    }
+
+    /// The system registered with the name.
+    pub fn get(&self, name: &str) -> Option<&(dyn RotationSystem + Send + Sync)> {
+        self.systems.iter().find(|(n, _)| n == name).map(|(_, system)| &**system)
+    }
+
+    /// The names of the registered systems, in the order they were first registered.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.systems.iter().map(|(name, _)| &**name)
+    }
+}
+
+impl Default for RotationSystems {
+    fn default() -> Self {
+        let mut systems = RotationSystems::empty();
+        systems.register("srs", Srs);
+        systems.register("srs+", SrsPlus);
+        systems.register("ars", Ars);
+        systems.register("classic", Classic);
+        systems
+    }
+}
+
+impl fmt::Debug for RotationSystems {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RotationSystems").field(&self.names().collect::<Vec<_>>()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    const KINDS: [PieceType; 7] = [
+        PieceType::I, PieceType::L, PieceType::O, PieceType::Z,
+        PieceType::T, PieceType::J, PieceType::S
+    ];
+    const ROTATIONS: [RotationState; 4] = [
+        RotationState::North, RotationState::East, RotationState::South, RotationState::West
+    ];
+
+    fn piece(kind: PieceType, rotation: RotationState, x: i32, y: i32) -> Piece {
+        Piece { kind, rotation, x, y }
+    }
+
+    fn sorted_cells(piece: &Piece) -> [(i32, i32); 4] {
+        let mut cells = piece.cells();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn srs_plus() {
+        let page = Page::default();
+        let t = piece(PieceType::T, RotationState::North, 4, 0);
+        assert_eq!(page.rotate(&t, RotationDirection::Flip, &Srs), None);
+        assert_eq!(
+            page.rotate(&t, RotationDirection::Flip, &SrsPlus),
+            Some(piece(PieceType::T, RotationState::South, 4, 1))
+        );
+
+        // SRS+ I kicks are mirror images of each other
+        let i = piece(PieceType::I, RotationState::North, 1, 0);
+        assert_eq!(
+            page.rotate(&i, RotationDirection::Cw, &SrsPlus),
+            Some(piece(PieceType::I, RotationState::East, 3, 2))
+        );
+        let mirrored = piece(PieceType::I, RotationState::North, 7, 0);
+        assert_eq!(
+            page.rotate(&mirrored, RotationDirection::Ccw, &SrsPlus),
+            Some(piece(PieceType::I, RotationState::West, 6, 1))
+        );
+    }
+
+    #[test]
+    fn ars() {
+        let page = Page::default();
+        assert_eq!(Ars.spawn_rotation(PieceType::T), RotationState::South);
+        assert_eq!(Ars.spawn_rotation(PieceType::I), RotationState::North);
+
+        // pieces rotate within the bottom of their bounding box
+        let t = piece(PieceType::T, RotationState::South, 4, 1);
+        let rotated = page.rotate(&t, RotationDirection::Cw, &Ars).unwrap();
+        let rotated = page.rotate(&rotated, RotationDirection::Cw, &Ars).unwrap();
+        assert_eq!(rotated, piece(PieceType::T, RotationState::North, 4, 0));
+        assert_eq!(page.rotate(&t, RotationDirection::Flip, &Ars), None);
+
+        // S pieces have two orientations
+        let s = piece(PieceType::S, RotationState::North, 4, 0);
+        let cw = page.rotate(&s, RotationDirection::Cw, &Ars).unwrap();
+        let ccw = page.rotate(&s, RotationDirection::Ccw, &Ars).unwrap();
+        assert_eq!(sorted_cells(&cw), sorted_cells(&ccw));
+
+        // kicked off of the wall
+        let l = piece(PieceType::L, RotationState::East, 0, 1);
+        assert_eq!(
+            page.rotate(&l, RotationDirection::Cw, &Ars),
+            Some(piece(PieceType::L, RotationState::South, 1, 1))
+        );
+
+        // but not when the middle column is blocked first
+        let mut page = Page::default();
+        page.field[2][4] = CellColor::Grey;
+        let t = piece(PieceType::T, RotationState::South, 4, 1);
+        assert!(page.collides(&piece(PieceType::T, RotationState::East, 4, 1)));
+        assert!(!page.collides(&piece(PieceType::T, RotationState::East, 3, 1)));
+        assert_eq!(page.rotate(&t, RotationDirection::Ccw, &Ars), None);
+        let mut page = Page::default();
+        page.field[2][3] = CellColor::Grey;
+        let l = piece(PieceType::L, RotationState::South, 4, 1);
+        assert_eq!(
+            page.rotate(&l, RotationDirection::Cw, &Ars),
+            Some(piece(PieceType::L, RotationState::West, 5, 1))
+        );
+    }
+
+    #[test]
+    fn classic() {
+        let page = Page::default();
+        for &kind in &KINDS {
+            let spawn = piece(kind, Classic.spawn_rotation(kind), 4, 10);
+            let cw = page.rotate(&spawn, RotationDirection::Cw, &Classic).unwrap();
+            let ccw = page.rotate(&spawn, RotationDirection::Ccw, &Classic).unwrap();
+            assert_eq!(page.rotate(&spawn, RotationDirection::Flip, &Classic), None);
+            match kind {
+                PieceType::I | PieceType::S | PieceType::Z => {
+                    assert_eq!(sorted_cells(&cw), sorted_cells(&ccw));
+                    let back = page.rotate(&cw, RotationDirection::Cw, &Classic).unwrap();
+                    assert_eq!(sorted_cells(&back), sorted_cells(&spawn));
+                }
+                PieceType::O => assert_eq!(sorted_cells(&cw), sorted_cells(&spawn)),
+                _ => assert_eq!((cw.x, cw.y, ccw.x, ccw.y), (4, 10, 4, 10))
+            }
+        }
+
+        // no kicks
+        let t = piece(PieceType::T, RotationState::East, 0, 1);
+        assert_eq!(page.rotate(&t, RotationDirection::Cw, &Classic), None);
+    }
+
+    #[test]
+    fn rotations_undo() {
+        let page = Page::default();
+        let systems = RotationSystems::default();
+        for name in systems.names() {
+            let system = systems.get(name).unwrap();
+            for &kind in &KINDS {
+                for &rotation in &ROTATIONS {
+                    let piece = piece(kind, rotation, 4, 10);
+                    let cw = page.rotate(&piece, RotationDirection::Cw, system).unwrap();
+                    let back = page.rotate(&cw, RotationDirection::Ccw, system).unwrap();
+                    assert_eq!(sorted_cells(&back), sorted_cells(&piece), "{} {:?}", name, piece);
+                }
+            }
+        }
+    }
+
+    struct Stuck;
+
+    impl RotationSystem for Stuck {
+        fn kicks(&self, _: PieceType, _: RotationState, _: RotationDirection) -> &[(i32, i32)] {
+            &[]
+        }
+    }
+
+    #[test]
+    fn custom_systems() {
+        let mut systems = RotationSystems::default();
+        assert_eq!(systems.names().collect::<Vec<_>>(), ["srs", "srs+", "ars", "classic"]);
+        systems.register("stuck", Stuck);
+        systems.register("srs", SrsPlus);
+        assert_eq!(systems.names().count(), 5);
+        assert!(systems.get("tgm").is_none());
+
+        let page = Page::default();
+        let t = piece(PieceType::T, RotationState::North, 4, 0);
+        assert_eq!(page.rotate(&t, RotationDirection::Cw, systems.get("stuck").unwrap()), None);
+        assert!(page.rotate(&t, RotationDirection::Flip, systems.get("srs").unwrap()).is_some());
+        assert_eq!(
+            Srs.kicks(PieceType::T, RotationState::North, RotationDirection::Cw),
+            &*Srs::JLSTZ.cw[RotationState::North as usize]
+        );
+
+        let mut table = Srs::JLSTZ;
+        table.cw[0].to_mut().push((0, 1));
+        assert_eq!(table.kicks(RotationState::South, RotationDirection::Cw).len(), 6);
+        assert_eq!(Srs::JLSTZ.kicks(RotationState::South, RotationDirection::Cw).len(), 5);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn kick_table_serde() {
+        let json = serde_json::to_value(Srs::I).unwrap();
+        assert_eq!(json["flip"][0], serde_json::json!([[-1, 1]]));
+        let table: KickTable = serde_json::from_value(json).unwrap();
+        assert_eq!(table, Srs::I);
+    }
+}
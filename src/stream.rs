@@ -0,0 +1,347 @@
+use crate::{
+    decode_piece, js_unescape, truncated_page, DecodeFumenError, Fumen, Page, PageEncoding,
+    Prefix, Reader
+};
+
+/// Limits on the resources used while decoding, for decoding untrusted data.
+///
+/// A short string can describe a huge number of pages, so these should be set when decoding data
+/// from untrusted sources. The default has no limits.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecodeOptions {
+    /// The maximum number of pages to decode.
+    pub max_pages: Option<usize>,
+    /// The maximum total length in bytes of the comments of all pages, after unescaping.
+    pub max_comment_bytes: Option<usize>,
+    /// The maximum length in bytes of the data, including the prefix.
+    pub max_input_length: Option<usize>
+}
+
+/// A limit from [`DecodeOptions`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Limit {
+    Pages,
+    CommentBytes,
+    InputLength
+}
+
+/// Decodes fumen data one page at a time.
+///
+/// Only the previous page is kept around, so this can be used to scan through fumens with many
+/// pages without holding all of them in memory. The pages are the same as those in the result of
+/// [`Fumen::decode`](crate::Fumen::decode). After an error is returned, the iterator ends.
+#[derive(Clone, Debug)]
+pub struct FumenReader<'a> {
+    reader: Reader<'a>,
+    prefix: Prefix,
+    options: DecodeOptions,
+    previous: Option<Page>,
+    /// The number of following pages which reuse the previous field.
+    empty_fields: usize,
+    guideline: bool,
+    pages_read: usize,
+    comment_bytes: usize,
+    failed: bool
+}
+
+impl<'a> FumenReader<'a> {
+    /// Starts decoding a fumen data string, failing if it doesn't start with a valid prefix.
+    pub fn new(data: &'a str) -> Result<FumenReader<'a>, DecodeFumenError> {
+        FumenReader::with_options(data, DecodeOptions::default())
+    }
+
+    /// Starts decoding a fumen data string with limits on the resources used.
+    ///
+    /// Exceeding a limit is reported as [`DecodeFumenError::LimitExceeded`].
+    pub fn with_options(
+        data: &'a str, options: DecodeOptions
+    ) -> Result<FumenReader<'a>, DecodeFumenError> {
+        if let Some(max) = options.max_input_length.filter(|&max| data.len() > max) {
+            return Err(DecodeFumenError::LimitExceeded {
+                offset: max, page: 0, limit: Limit::InputLength
+            });
+        }
+        let prefix = Prefix::parse(data)
+            .ok_or(DecodeFumenError::UnsupportedVersion { offset: 0, page: 0 })?;
+        Ok(FumenReader {
+            reader: Reader {
+                data,
+                offset: Prefix::LENGTH,
+                page: 0,
+                field_height: prefix.version.field_height(),
+                separators: None
+            },
+            prefix,
+            options,
+            previous: None,
+            empty_fields: 0,
+            guideline: true,
+            pages_read: 0,
+            comment_bytes: 0,
+            failed: false
+        })
+    }
+
+    /// The prefix the data started with.
+    pub fn prefix(&self) -> Prefix {
+        self.prefix
+    }
+
+    /// The guideline flag of the fumen, which is known once the first page has been read.
+    pub fn guideline(&self) -> bool {
+        self.guideline
+    }
+
+    /// The number of pages successfully read so far.
+    pub fn pages_read(&self) -> usize {
+        self.pages_read
+    }
+
+    /// The offset into the data of the next page.
+    pub fn offset(&self) -> usize {
+        self.reader.offset
+    }
+
+    /// Starts keeping track of where `?` separators are, for lossless decoding.
+    pub(crate) fn track_separators(&mut self) {
+        self.reader.separators = Some(vec![]);
+    }
+
+    pub(crate) fn into_separators(self) -> Option<Vec<usize>> {
+        self.reader.separators
+    }
+
+    /// Reads the next page, recording how it was encoded in `encoding` if it is given.
+    pub(crate) fn read_page(
+        &mut self, encoding: Option<&mut PageEncoding>
+    ) -> Option<Result<Page, DecodeFumenError>> {
+        if self.failed || self.reader.at_end() {
+            return None;
+        }
+        let result = self.read_page_inner(encoding);
+        self.failed = result.is_err();
+        Some(result)
+    }
+
+    fn read_page_inner(
+        &mut self, mut encoding: Option<&mut PageEncoding>
+    ) -> Result<Page, DecodeFumenError> {
+        let pages_read = self.pages_read;
+        if self.options.max_pages.is_some_and(|max| pages_read >= max) {
+            return Err(DecodeFumenError::LimitExceeded {
+                offset: self.reader.offset, page: pages_read, limit: Limit::Pages
+            });
+        }
+        let reader = &mut self.reader;
+        let mut page = match self.previous {
+            Some(ref p) => {
+                reader.page += 1;
+                p.next_page()
+            }
+            None => Page::default()
+        };
+
+        if self.empty_fields == 0 {
+            let runs = encoding.as_mut().map(|e| e.field.insert(vec![]));
+            self.empty_fields = reader.field(&mut page, runs)?;
+            if let Some(ref mut encoding) = encoding {
+                encoding.empty_fields = self.empty_fields;
+            }
+        } else {
+            self.empty_fields -= 1;
+        }
+
+        // decode page data
+        let start = reader.offset;
+        let number = reader.number(3, truncated_page)?;
+
+        page.piece = decode_piece(number, reader.field_height).map_err(|()| {
+            DecodeFumenError::InvalidPiecePosition { offset: start, page: reader.page }
+        })?;
+
+        let flags = number / 32 / reader.cells();
+        page.rise = flags & 0b1 != 0;
+        page.mirror = flags & 0b10 != 0;
+        let guideline = flags & 0b100 != 0;
+        let comment = flags & 0b1000 != 0;
+        page.lock = flags & 0b10000 == 0;
+
+        if comment {
+            let start = reader.offset;
+            let escaped = reader.comment()?;
+            let comment = js_unescape(&escaped);
+            let comment_bytes = self.comment_bytes + comment.len();
+            self.comment_bytes = comment_bytes;
+            if self.options.max_comment_bytes.is_some_and(|max| comment_bytes > max) {
+                return Err(DecodeFumenError::LimitExceeded {
+                    offset: start, page: reader.page, limit: Limit::CommentBytes
+                });
+            }
+            page.comment = Some(comment);
+            if let Some(ref mut encoding) = encoding {
+                encoding.comment = Some(escaped);
+            }
+        }
+
+        if self.previous.is_none() {
+            self.guideline = guideline;
+        }
+        if let Some(encoding) = encoding {
+            encoding.action = number;
+        }
+        self.previous = Some(page.clone());
+        self.pages_read += 1;
+        Ok(page)
+    }
+}
+
+impl Fumen {
+    /// Decodes as much of a damaged fumen data string as possible.
+    ///
+    /// Returns every page before the first error, along with the error if there was one. This is
+    /// useful for recovering the start of a fumen from a link which was cut off. The guideline
+    /// flag defaults to on if the first page couldn't be decoded.
+    pub fn decode_partial(data: &str) -> (Fumen, Option<DecodeFumenError>) {
+        Fumen::decode_partial_with_options(data, DecodeOptions::default())
+    }
+
+    /// Decodes as much of a damaged fumen data string as possible, with limits on the resources
+    /// used.
+    ///
+    /// The pages before a limit is exceeded are returned along with the
+    /// [`DecodeFumenError::LimitExceeded`] error. See [`Fumen::decode_partial`] for details.
+    pub fn decode_partial_with_options(
+        data: &str, options: DecodeOptions
+    ) -> (Fumen, Option<DecodeFumenError>) {
+        let mut fumen = Fumen::default();
+        let mut reader = match FumenReader::with_options(data, options) {
+            Ok(reader) => reader,
+            Err(e) => return (fumen, Some(e))
+        };
+        let mut error = None;
+        for page in &mut reader {
+            match page {
+                Ok(page) => fumen.pages.push(page),
+                Err(e) => error = Some(e)
+            }
+        }
+        fumen.guideline = reader.guideline();
+        (fumen, error)
+    }
+}
+
+impl Iterator for FumenReader<'_> {
+    type Item = Result<Page, DecodeFumenError>;
+
+    fn next(&mut self) -> Option<Result<Page, DecodeFumenError>> {
+        self.read_page(None)
+    }
+}
+
+impl std::iter::FusedIterator for FumenReader<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn read_pages() {
+        let mut fumen = Fumen::default();
+        fumen.add_page().comment = Some("Hello World!".to_owned());
+        for _ in 0..5 {
+            fumen.add_page().field[0][0] = CellColor::Grey;
+        }
+        let data = fumen.encode();
+        let pages: Result<Vec<_>, _> = FumenReader::new(&data).unwrap().collect();
+        assert_eq!(pages.unwrap(), fumen.pages);
+
+        let mut reader = FumenReader::new(&data).unwrap();
+        assert_eq!(reader.pages_read(), 0);
+        assert_eq!(reader.by_ref().take(2).count(), 2);
+        assert_eq!(reader.pages_read(), 2);
+        assert!(reader.guideline());
+        assert_eq!(reader.count(), 4);
+    }
+
+    #[test]
+    fn read_errors() {
+        assert_eq!(
+            FumenReader::new("v116@").unwrap_err(),
+            DecodeFumenError::UnsupportedVersion { offset: 0, page: 0 }
+        );
+        let mut reader = FumenReader::new("v115@vhBAgHA").unwrap();
+        assert_eq!(reader.prefix(), Prefix { mode: ViewMode::View, version: Version::V115 });
+        assert!(reader.next().unwrap().is_ok());
+        assert_eq!(
+            reader.next(),
+            Some(Err(DecodeFumenError::TruncatedPage { offset: 12, page: 1 }))
+        );
+        assert_eq!(reader.next(), None);
+    }
+
+    #[test]
+    fn partial() {
+        let mut fumen = Fumen { guideline: false, ..Fumen::default() };
+        fumen.add_page().comment = Some("Hello World!".to_owned());
+        fumen.add_page().field[0][0] = CellColor::Grey;
+        fumen.add_page().comment = Some("cut off".to_owned());
+        let data = fumen.encode();
+        assert_eq!(Fumen::decode_partial(&data), (fumen.clone(), None));
+
+        let cut = &data[..data.len() - 4];
+        let (partial, error) = Fumen::decode_partial(cut);
+        assert_eq!(partial.pages, fumen.pages[..2]);
+        assert!(!partial.guideline);
+        assert_eq!(error, Some(DecodeFumenError::TruncatedComment { offset: cut.len(), page: 2 }));
+
+        assert_eq!(
+            Fumen::decode_partial("v115@vh"),
+            (Fumen::default(), Some(DecodeFumenError::TruncatedField { offset: 7, page: 0 }))
+        );
+        assert_eq!(
+            Fumen::decode_partial("https://"),
+            (Fumen::default(), Some(DecodeFumenError::UnsupportedVersion { offset: 0, page: 0 }))
+        );
+    }
+
+    #[test]
+    fn limits() {
+        // an empty field reused by 63 more pages
+        let data = format!("v115@vh/AgH{}", "AAA".repeat(63));
+        let data = &data[..];
+        assert_eq!(Fumen::decode(data).map(|f| f.pages.len()), Ok(64));
+        let options = DecodeOptions { max_pages: Some(10), ..DecodeOptions::default() };
+        assert_eq!(
+            Fumen::decode_with_options(data, options),
+            Err(DecodeFumenError::LimitExceeded { offset: 38, page: 10, limit: Limit::Pages })
+        );
+        assert_eq!(FumenReader::with_options(data, options).unwrap().take(10).count(), 10);
+        let (partial, error) = Fumen::decode_partial_with_options(data, options);
+        assert_eq!(partial.pages.len(), 10);
+        assert_eq!(
+            error,
+            Some(DecodeFumenError::LimitExceeded { offset: 38, page: 10, limit: Limit::Pages })
+        );
+        assert_eq!(
+            Fumen::decode_lossless_with_options(data, options),
+            Err(DecodeFumenError::LimitExceeded { offset: 38, page: 10, limit: Limit::Pages })
+        );
+
+        let options = DecodeOptions { max_input_length: Some(100), ..DecodeOptions::default() };
+        assert_eq!(
+            Fumen::decode_with_options(data, options),
+            Err(DecodeFumenError::LimitExceeded { offset: 100, page: 0, limit: Limit::InputLength })
+        );
+
+        let data = "v115@vhBAgWQAIoMDEvoo2AXXaDEkoA6AAgWQAIoMDEvoo2AXXaDEkoA6A";
+        let options = DecodeOptions { max_comment_bytes: Some(20), ..DecodeOptions::default() };
+        assert_eq!(
+            Fumen::decode_with_options(data, options),
+            Err(DecodeFumenError::LimitExceeded { offset: 36, page: 1, limit: Limit::CommentBytes })
+        );
+        let options = DecodeOptions { max_comment_bytes: Some(24), ..DecodeOptions::default() };
+        assert!(Fumen::decode_with_options(data, options).is_ok());
+    }
+}
@@ -0,0 +1,137 @@
+//! Serializes a [`Fumen`] as its pages instead of as an encoded string.
+//!
+//! By default a [`Fumen`] serializes as the string [`Fumen::encode`] produces. Use this module
+//! with `#[serde(with = "fumen::structured")]` to serialize it as a structure with `pages` and
+//! `guideline` fields instead, where fields are arrays of rows from the bottom up and pieces are
+//! `{kind, rotation, x, y}`.
+//!
+//! ```
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Response {
+//!     #[serde(with = "fumen::structured")]
+//!     fumen: fumen::Fumen
+//! }
+//! ```
+//!
+//! The [`Encoding`](crate::Encoding) of the fumen is not included, so a fumen deserialized from
+//! this form encodes canonically. Like [`Fumen::decode`], deserializing fails if a piece lies
+//! partly outside of the field, since such a fumen can't be encoded.
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Diagnostic, Fumen, Page, PageProblem};
+
+#[derive(Serialize)]
+struct FumenRef<'a> {
+    pages: &'a [Page],
+    guideline: bool
+}
+
+#[derive(Deserialize)]
+struct FumenOwned {
+    pages: Vec<Page>,
+    #[serde(default = "default_guideline")]
+    guideline: bool
+}
+
+fn default_guideline() -> bool {
+    true
+}
+
+/// Serializes the fumen as a structure.
+pub fn serialize<S: Serializer>(fumen: &Fumen, ser: S) -> Result<S::Ok, S::Error> {
+    FumenRef { pages: &fumen.pages, guideline: fumen.guideline }.serialize(ser)
+}
+
+/// Deserializes a fumen from a structure. The guideline flag defaults to on if it is missing.
+///
+/// Fails if a page's piece lies partly outside of the field.
+pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Fumen, D::Error> {
+    let FumenOwned { pages, guideline } = FumenOwned::deserialize(de)?;
+    for (page, p) in pages.iter().enumerate() {
+        if let Some(problem) = p.validate().into_iter()
+            .find(|problem| *problem == PageProblem::PieceOutOfBounds)
+        {
+            return Err(D::Error::custom(Diagnostic { page, problem }));
+        }
+    }
+    Ok(Fumen { pages, guideline, encoding: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use super::FumenRef;
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Both {
+        encoded: Fumen,
+        #[serde(with = "crate::structured")]
+        structured: Fumen
+    }
+
+    #[test]
+    fn json() {
+        let mut fumen = Fumen::default();
+        let page = fumen.add_page();
+        page.field[0][9] = CellColor::Grey;
+        page.piece = Some(
+            Piece { kind: PieceType::T, rotation: RotationState::North, x: 4, y: 0 }
+        );
+        page.comment = Some("hi".to_owned());
+        let both = Both { encoded: fumen.clone(), structured: fumen.clone() };
+
+        let json = serde_json::to_value(&both).unwrap();
+        assert_eq!(json["encoded"], fumen.encode());
+        let structured = &json["structured"];
+        assert_eq!(structured["guideline"], true);
+        let page = &structured["pages"][0];
+        assert_eq!(
+            page["piece"],
+            serde_json::json!({ "kind": "T", "rotation": "North", "x": 4, "y": 0 })
+        );
+        assert_eq!(page["field"].as_array().unwrap().len(), 23);
+        assert_eq!(page["field"][0][9], "Grey");
+        assert_eq!(page["lock"], true);
+        assert_eq!(page["comment"], "hi");
+
+        assert_eq!(serde_json::from_value::<Both>(json).unwrap(), both);
+    }
+
+    #[test]
+    fn defaults_and_errors() {
+        let json = serde_json::json!({ "encoded": "v115@", "structured": { "pages": [] } });
+        let both: Both = serde_json::from_value(json).unwrap();
+        assert_eq!(both.structured, Fumen::default());
+
+        let garbage_row = vec!["Empty"; 10];
+        let json = serde_json::json!({ "encoded": "v115@", "structured": { "pages": [{
+            "piece": null,
+            "field": [],
+            "garbage_row": garbage_row,
+            "rise": false,
+            "mirror": false,
+            "lock": true,
+            "comment": null
+        }] } });
+        assert!(serde_json::from_value::<Both>(json).is_err());
+        assert!(serde_json::from_str::<Fumen>("\"v115@vh\"").is_err());
+
+        let mut fumen = Fumen::default();
+        fumen.add_page();
+        fumen.add_page().piece = Some(
+            Piece { kind: PieceType::I, rotation: RotationState::North, x: 9, y: 0 }
+        );
+        let json = serde_json::json!({
+            "encoded": "v115@",
+            "structured": serde_json::to_value(FumenRef {
+                pages: &fumen.pages,
+                guideline: true
+            }).unwrap()
+        });
+        let error = serde_json::from_value::<Both>(json).unwrap_err();
+        assert_eq!(error.to_string(), "page 2: piece is outside of the field");
+        let error = serde_json::to_string(&fumen).unwrap_err();
+        assert_eq!(error.to_string(), EncodeFumenError::PieceOutOfBounds { page: 1 }.to_string());
+    }
+}
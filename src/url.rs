@@ -0,0 +1,216 @@
+use crate::{DecodeFumenError, DecodeOptions, Fumen, Prefix};
+
+/// A website which hosts a fumen viewer.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Host {
+    /// The original fumen at `http://fumen.zui.jp/`.
+    FumenZui,
+    /// Hard Drop's copy of fumen at `https://harddrop.com/fumen/`.
+    HardDrop,
+    /// knewjade's fumen for mobile at `https://knewjade.github.io/fumen-for-mobile/`.
+    FumenForMobile
+}
+
+impl Host {
+    /// Everything in a link to this host that comes before the fumen data.
+    fn url_prefix(self) -> &'static str {
+        match self {
+            Host::FumenZui => "http://fumen.zui.jp/?",
+            Host::HardDrop => "https://harddrop.com/fumen/?",
+            Host::FumenForMobile => "https://knewjade.github.io/fumen-for-mobile/#?d="
+        }
+    }
+
+    /// Finds which host a link points to from the part of the link before the fumen data.
+    pub(crate) fn detect(url: &str) -> Option<Host> {
+        if url.contains("fumen.zui.jp") {
+            Some(Host::FumenZui)
+        } else if url.contains("harddrop.com/fumen") {
+            Some(Host::HardDrop)
+        } else if url.contains("knewjade.github.io/fumen-for-mobile") {
+            Some(Host::FumenForMobile)
+        } else {
+            None
+        }
+    }
+}
+
+impl Fumen {
+    /// Decodes a fumen from a link to a fumen viewer, or from bare fumen data.
+    ///
+    /// The fumen data is found by looking for a prefix such as `v115@`, so any host, query or
+    /// fragment wrapped around it is ignored, as is trailing punctuation. URL encoding is undone
+    /// before decoding. Offsets in the returned error are relative to `url`.
+    pub fn from_url(url: &str) -> Result<Fumen, DecodeFumenError> {
+        Fumen::from_url_with_options(url, DecodeOptions::default())
+    }
+
+    /// Decodes a fumen from a link to a fumen viewer, with limits on the resources used.
+    ///
+    /// The limits apply to the fumen data found in the link. See [`Fumen::from_url`] and
+    /// [`Fumen::decode_with_options`] for details.
+    pub fn from_url_with_options(
+        url: &str, options: DecodeOptions
+    ) -> Result<Fumen, DecodeFumenError> {
+        let (text, origins) = percent_decode(url);
+        let data = find_data(&text, 0)
+            .ok_or(DecodeFumenError::UnsupportedVersion { offset: 0, page: 0 })?;
+        Fumen::decode_with_options(&text[data.clone()], options).map_err(|mut e| {
+            let offset = e.offset_mut();
+            *offset = origins[data.start + *offset];
+            e
+        })
+    }
+
+    /// Creates a link which opens this fumen on the given host.
+    ///
+    /// See [`Fumen::encode`] for details.
+    pub fn to_url(&self, host: Host) -> String {
+        let mut url = host.url_prefix().to_owned();
+        self.encode_into(&mut url).unwrap();
+        url
+    }
+}
+
+impl std::str::FromStr for Fumen {
+    type Err = DecodeFumenError;
+
+    /// Same as [`Fumen::from_url`].
+    fn from_str(s: &str) -> Result<Fumen, DecodeFumenError> {
+        Fumen::from_url(s)
+    }
+}
+
+impl std::fmt::Display for Fumen {
+    /// Writes the same data string as [`Fumen::encode`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a piece lies outside of the field. Use [`Fumen::try_encode`] to get an error
+    /// instead.
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.encode_into(fmt)
+    }
+}
+
+/// Undoes URL encoding of ASCII characters.
+///
+/// Also returns the offset into `text` of each byte of the result, plus the length of `text`.
+pub(crate) fn percent_decode(text: &str) -> (String, Vec<usize>) {
+    let mut result = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len() + 1);
+    let mut iter = text.char_indices();
+    while let Some((i, c)) = iter.next() {
+        let escaped = match c {
+            '%' => text.get(i+1..i+3)
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .filter(|b| b.is_ascii()),
+            _ => None
+        };
+        match escaped {
+            Some(b) => {
+                iter.next();
+                iter.next();
+                result.push(b as char);
+                origins.push(i);
+            }
+            None => {
+                result.push(c);
+                origins.extend(i..i + c.len_utf8());
+            }
+        }
+    }
+    origins.push(text.len());
+    (result, origins)
+}
+
+/// Finds the first fumen data in `text` at or after byte offset `from`.
+///
+/// The data must start with a valid prefix which isn't part of a longer word, and ends at the
+/// first character that can't be part of fumen data. Trailing `?` separators are not included.
+pub(crate) fn find_data(text: &str, from: usize) -> Option<std::ops::Range<usize>> {
+    let start = text[from..].char_indices()
+        .map(|(i, _)| from + i)
+        .find(|&i| {
+            Prefix::parse(&text[i..]).is_some() &&
+                !text[..i].ends_with(|c: char| c.is_alphanumeric())
+        })?;
+    let body = start + Prefix::LENGTH;
+    let length = text[body..].find(|c: char| !is_data_char(c)).unwrap_or(text.len() - body);
+    let end = body + text[body..body + length].trim_end_matches('?').len();
+    Some(start..end)
+}
+
+/// Whether `c` can appear in the data after the prefix.
+pub(crate) fn is_data_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '?'
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    const DATA: &str = "v115@vhAAgWQAIoMDEvoo2AXXaDEkoA6A";
+
+    fn hello_world() -> Fumen {
+        let mut fumen = Fumen::default();
+        fumen.add_page().comment = Some("Hello World!".to_owned());
+        fumen
+    }
+
+    #[test]
+    fn from_url() {
+        for url in &[
+            "v115@vhAAgWQAIoMDEvoo2AXXaDEkoA6A",
+            "http://fumen.zui.jp/?v115@vhAAgWQAIoMDEvoo2AXXaDEkoA6A",
+            "https://harddrop.com/fumen/?v115@vhAAgWQAIoMDEvoo2AXXaDEkoA6A",
+            "https://knewjade.github.io/fumen-for-mobile/#?d=v115@vhAAgWQAIoMDEvoo2AXXaDEkoA6A",
+            "http://fumen.zui.jp/?v115%40vhAAgWQAIoMDEvoo2AXXaDEkoA6A",
+            "<http://fumen.zui.jp/?v115@vhAAgWQAIoMDEvoo2AXXaDEkoA6A>.",
+            "(http://fumen.zui.jp/?v115@vhAAgWQA?IoMDEvoo2AXXaDEkoA6A?)",
+        ] {
+            assert_eq!(Fumen::from_url(url), Ok(hello_world()), "{}", url);
+            assert_eq!(url.parse(), Ok(hello_world()), "{}", url);
+        }
+        assert_eq!(
+            Fumen::from_url("http://fumen.zui.jp/?m115%40vhAAgWQA%2B"),
+            Err(DecodeFumenError::TruncatedComment { offset: 39, page: 0 })
+        );
+        assert_eq!(
+            Fumen::from_url("http://fumen.zui.jp/"),
+            Err(DecodeFumenError::UnsupportedVersion { offset: 0, page: 0 })
+        );
+        assert_eq!(
+            Fumen::from_url("Café fun"),
+            Err(DecodeFumenError::UnsupportedVersion { offset: 0, page: 0 })
+        );
+        assert_eq!(Fumen::from_url("café v115@vhAAgH"), Fumen::decode("v115@vhAAgH"));
+        let options = DecodeOptions { max_input_length: Some(10), ..DecodeOptions::default() };
+        assert_eq!(
+            Fumen::from_url_with_options("http://fumen.zui.jp/?v115@vhAAgH", options),
+            Err(DecodeFumenError::LimitExceeded { offset: 31, page: 0, limit: Limit::InputLength })
+        );
+    }
+
+    #[test]
+    fn to_url() {
+        let fumen = hello_world();
+        assert_eq!(fumen.to_string(), DATA);
+        for &host in &[Host::FumenZui, Host::HardDrop, Host::FumenForMobile] {
+            let url = fumen.to_url(host);
+            assert!(url.ends_with(DATA));
+            assert_eq!(Host::detect(&url), Some(host));
+            assert_eq!(Fumen::from_url(&url), Ok(fumen.clone()));
+        }
+
+        // overlapping pieces are written like encode writes them
+        let mut fumen = Fumen::default();
+        let page = fumen.add_page();
+        page.field[0][0] = CellColor::Grey;
+        page.piece = Some(Piece { kind: PieceType::T, rotation: RotationState::North, x: 1, y: 0 });
+        assert_eq!(fumen.to_string(), fumen.encode());
+        assert_eq!(Fumen::decode(&fumen.encode()).unwrap().to_string(), fumen.encode());
+    }
+}